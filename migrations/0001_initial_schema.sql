@@ -0,0 +1,32 @@
+-- The schema from before migrations, written so it can also be applied to a database that was
+-- created from the old db/init.sql
+CREATE TABLE IF NOT EXISTS users (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR(255) unique NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS songs (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    artist VARCHAR(255) NOT NULL,
+    uri VARCHAR(255) UNIQUE NOT NULL,
+    album_cover_url TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS rankings (
+    user_id INT REFERENCES users(id) ON DELETE CASCADE,
+    song_id INT REFERENCES songs(id) ON DELETE CASCADE,
+    rank INT CHECK (rank >= 1 AND rank <= 10),
+    PRIMARY KEY (user_id, song_id)
+);
+
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_constraint WHERE conname = 'unique_name_artist') THEN
+        ALTER TABLE songs ADD CONSTRAINT unique_name_artist UNIQUE (name, artist);
+    END IF;
+    IF NOT EXISTS (SELECT 1 FROM pg_constraint WHERE conname = 'unique_user_rank') THEN
+        ALTER TABLE rankings ADD CONSTRAINT unique_user_rank UNIQUE (user_id, rank);
+    END IF;
+END
+$$;
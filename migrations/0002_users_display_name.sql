@@ -0,0 +1,2 @@
+-- Queried by get_music_taste_overview but never part of db/init.sql
+ALTER TABLE users ADD COLUMN IF NOT EXISTS display_name VARCHAR(255);
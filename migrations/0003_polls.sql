@@ -0,0 +1,26 @@
+CREATE TABLE polls (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR(255) UNIQUE NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    closed_at TIMESTAMPTZ
+);
+
+-- Only one poll can be taking votes at a time
+CREATE UNIQUE INDEX one_open_poll ON polls ((closed_at IS NULL)) WHERE closed_at IS NULL;
+
+-- Snapshot of every ballot when a poll closes
+CREATE TABLE poll_rankings (
+    poll_id INT REFERENCES polls(id) ON DELETE CASCADE,
+    user_id INT REFERENCES users(id) ON DELETE CASCADE,
+    song_id INT REFERENCES songs(id) ON DELETE CASCADE,
+    rank INT NOT NULL,
+    PRIMARY KEY (poll_id, user_id, song_id)
+);
+
+-- Final countdown of a closed poll, position 1 is the winner
+CREATE TABLE poll_results (
+    poll_id INT REFERENCES polls(id) ON DELETE CASCADE,
+    song_id INT REFERENCES songs(id) ON DELETE CASCADE,
+    position INT NOT NULL,
+    PRIMARY KEY (poll_id, song_id)
+);
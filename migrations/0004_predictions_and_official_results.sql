@@ -0,0 +1,18 @@
+-- The official triple j countdown for a poll, position 1 is the winner
+CREATE TABLE official_results (
+    poll_id INT REFERENCES polls(id) ON DELETE CASCADE,
+    song_id INT REFERENCES songs(id) ON DELETE CASCADE,
+    position INT NOT NULL CHECK (position >= 1),
+    PRIMARY KEY (poll_id, song_id)
+);
+
+-- Guesses at the official countdown, kept apart from taste ballots in rankings
+CREATE TABLE predictions (
+    poll_id INT REFERENCES polls(id) ON DELETE CASCADE,
+    user_id INT REFERENCES users(id) ON DELETE CASCADE,
+    song_id INT REFERENCES songs(id) ON DELETE CASCADE,
+    position INT NOT NULL CHECK (position >= 1 AND position <= 100),
+    PRIMARY KEY (poll_id, user_id, song_id)
+);
+
+ALTER TABLE predictions ADD CONSTRAINT unique_user_prediction_position UNIQUE (poll_id, user_id, position);
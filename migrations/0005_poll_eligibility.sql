@@ -0,0 +1,13 @@
+-- Spotify gives the album release date as YYYY, YYYY-MM or YYYY-MM-DD depending on precision
+ALTER TABLE songs ADD COLUMN release_date VARCHAR(10);
+ALTER TABLE songs ADD COLUMN release_date_precision VARCHAR(5);
+
+ALTER TABLE polls ADD COLUMN eligible_from DATE;
+ALTER TABLE polls ADD COLUMN eligible_to DATE;
+
+-- Songs an admin has allowed into a poll regardless of release date
+CREATE TABLE eligibility_overrides (
+    poll_id INT REFERENCES polls(id) ON DELETE CASCADE,
+    uri VARCHAR(255) NOT NULL,
+    PRIMARY KEY (poll_id, uri)
+);
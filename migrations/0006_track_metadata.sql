@@ -0,0 +1,11 @@
+ALTER TABLE songs ADD COLUMN isrc VARCHAR(20);
+ALTER TABLE songs ADD COLUMN album_name VARCHAR(255);
+-- [{"id": ..., "name": ...}] in Spotify's order, the first is the primary artist
+ALTER TABLE songs ADD COLUMN artists JSONB;
+ALTER TABLE songs ADD COLUMN duration_ms INT;
+ALTER TABLE songs ADD COLUMN explicit BOOLEAN;
+ALTER TABLE songs ADD COLUMN popularity INT;
+ALTER TABLE songs ADD COLUMN preview_url TEXT;
+-- [{"url": ..., "width": ..., "height": ...}] for every album image size
+ALTER TABLE songs ADD COLUMN images JSONB;
+ALTER TABLE songs ADD COLUMN metadata_updated_at TIMESTAMPTZ;
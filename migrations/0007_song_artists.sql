@@ -0,0 +1,40 @@
+CREATE TABLE artists (
+    id SERIAL PRIMARY KEY,
+    spotify_id VARCHAR(64) UNIQUE,
+    name VARCHAR(255) NOT NULL
+);
+
+-- Every artist credited on a song, position 1 is the primary artist
+CREATE TABLE song_artists (
+    song_id INT REFERENCES songs(id) ON DELETE CASCADE,
+    artist_id INT REFERENCES artists(id) ON DELETE CASCADE,
+    role VARCHAR(10) NOT NULL CHECK (role IN ('primary', 'featured')),
+    position INT NOT NULL,
+    PRIMARY KEY (song_id, artist_id)
+);
+
+-- Credit existing songs from their Spotify artist list, or from songs.artist when we don't have one
+INSERT INTO artists (spotify_id, name)
+SELECT DISTINCT credit->>'id', credit->>'name'
+FROM songs s, JSONB_ARRAY_ELEMENTS(s.artists) AS credit
+WHERE credit->>'id' IS NOT NULL
+ON CONFLICT (spotify_id) DO NOTHING;
+
+INSERT INTO artists (name)
+SELECT DISTINCT s.artist FROM songs s
+WHERE s.artists IS NULL
+  AND NOT EXISTS (SELECT 1 FROM artists a WHERE LOWER(a.name) = LOWER(s.artist));
+
+INSERT INTO song_artists (song_id, artist_id, role, position)
+SELECT s.id, a.id, CASE WHEN credit.position = 1 THEN 'primary' ELSE 'featured' END, credit.position
+FROM songs s
+CROSS JOIN JSONB_ARRAY_ELEMENTS(s.artists) WITH ORDINALITY AS credit(value, position)
+JOIN artists a ON a.spotify_id = credit.value->>'id'
+ON CONFLICT DO NOTHING;
+
+INSERT INTO song_artists (song_id, artist_id, role, position)
+SELECT DISTINCT ON (s.id) s.id, a.id, 'primary', 1
+FROM songs s
+JOIN artists a ON LOWER(a.name) = LOWER(s.artist)
+WHERE s.artists IS NULL
+ORDER BY s.id, a.id;
@@ -0,0 +1,31 @@
+-- Versions of the same song (single, album track, radio edit, deluxe) point at one canonical
+-- row, NULL means the song is canonical itself
+ALTER TABLE songs ADD COLUMN normalized_name VARCHAR(255);
+ALTER TABLE songs ADD COLUMN canonical_id INT REFERENCES songs(id) ON DELETE SET NULL;
+CREATE INDEX songs_isrc ON songs (isrc);
+CREATE INDEX songs_normalized_name ON songs (normalized_name);
+
+-- Suggested merges found by title normalisation wait for an admin, ISRC matches are applied
+-- straight away and recorded as confirmed
+CREATE TABLE song_merge_suggestions (
+    id SERIAL PRIMARY KEY,
+    song_id INT NOT NULL REFERENCES songs(id) ON DELETE CASCADE,
+    canonical_id INT NOT NULL REFERENCES songs(id) ON DELETE CASCADE,
+    reason VARCHAR(10) NOT NULL CHECK (reason IN ('isrc', 'title')),
+    status VARCHAR(10) NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'confirmed', 'rejected')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    reviewed_at TIMESTAMPTZ,
+    reviewed_by VARCHAR(255),
+    UNIQUE (song_id, canonical_id)
+);
+
+-- Votes with every version folded into its canonical song. A voter who ranked two versions
+-- keeps their better rank.
+CREATE VIEW canonical_rankings AS
+SELECT DISTINCT ON (r.user_id, COALESCE(s.canonical_id, s.id))
+    r.user_id,
+    COALESCE(s.canonical_id, s.id) AS song_id,
+    r.rank
+FROM rankings r
+JOIN songs s ON r.song_id = s.id
+ORDER BY r.user_id, COALESCE(s.canonical_id, s.id), r.rank;
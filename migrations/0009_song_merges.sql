@@ -0,0 +1,24 @@
+-- Songs are identified by their Spotify URI. Other releases with the same name and artist get
+-- their own row and are folded together by canonical_id instead of overwriting the URI.
+ALTER TABLE songs DROP CONSTRAINT unique_name_artist;
+
+-- Admin splits are remembered as rejected manual suggestions
+ALTER TABLE song_merge_suggestions DROP CONSTRAINT song_merge_suggestions_reason_check;
+ALTER TABLE song_merge_suggestions ADD CONSTRAINT song_merge_suggestions_reason_check
+    CHECK (reason IN ('isrc', 'title', 'manual'));
+
+-- Admin merges move votes from one song to another. The rows that were moved or dropped are
+-- kept so the merge can be undone.
+CREATE TABLE song_merges (
+    id SERIAL PRIMARY KEY,
+    from_song_id INT NOT NULL REFERENCES songs(id) ON DELETE CASCADE,
+    into_song_id INT NOT NULL REFERENCES songs(id) ON DELETE CASCADE,
+    previous_canonical_id INT REFERENCES songs(id) ON DELETE SET NULL,
+    relinked_song_ids INT[] NOT NULL,
+    moved_rankings JSONB NOT NULL,
+    dropped_rankings JSONB NOT NULL,
+    merged_by VARCHAR(255) NOT NULL,
+    merged_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    undone_by VARCHAR(255),
+    undone_at TIMESTAMPTZ
+);
@@ -0,0 +1,2 @@
+-- Ballot validation rules for the poll, see BallotRules for the fields and defaults
+ALTER TABLE polls ADD COLUMN ballot_rules JSONB NOT NULL DEFAULT '{}';
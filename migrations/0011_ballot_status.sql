@@ -0,0 +1,19 @@
+-- A voter's ballot only counts once it's submitted, drafts can be saved as often as they like
+CREATE TABLE ballots (
+    user_id INT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    status VARCHAR(10) NOT NULL DEFAULT 'draft' CHECK (status IN ('draft', 'submitted')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    submitted_at TIMESTAMPTZ
+);
+
+-- Ballots saved before drafts existed already counted, so they start out submitted
+INSERT INTO ballots (user_id, status, submitted_at)
+SELECT DISTINCT user_id, 'submitted', NOW() FROM rankings
+ON CONFLICT DO NOTHING;
+
+-- canonical_rankings restricted to submitted ballots, for everything that counts votes
+CREATE VIEW submitted_rankings AS
+SELECT cr.user_id, cr.song_id, cr.rank
+FROM canonical_rankings cr
+JOIN ballots b ON b.user_id = cr.user_id AND b.status = 'submitted';
@@ -0,0 +1,25 @@
+-- Every save of a ballot is kept as a numbered revision with the full list as it stood
+ALTER TABLE ballots ADD COLUMN version INT NOT NULL DEFAULT 0;
+
+CREATE TABLE ballot_revisions (
+    user_id INT REFERENCES users(id) ON DELETE CASCADE,
+    version INT NOT NULL,
+    poll_id INT REFERENCES polls(id) ON DELETE SET NULL,
+    status VARCHAR(10) NOT NULL,
+    songs JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, version)
+);
+
+-- Start the history of existing ballots from what they hold now
+UPDATE ballots SET version = 1;
+
+INSERT INTO ballot_revisions (user_id, version, poll_id, status, songs)
+SELECT b.user_id, 1, (SELECT id FROM polls WHERE closed_at IS NULL), b.status,
+    COALESCE(
+        (SELECT JSONB_AGG(JSONB_BUILD_OBJECT('rank', r.rank, 'uri', s.uri, 'name', s.name, 'artist', s.artist) ORDER BY r.rank)
+         FROM rankings r JOIN songs s ON r.song_id = s.id
+         WHERE r.user_id = b.user_id),
+        '[]'
+    )
+FROM ballots b;
@@ -0,0 +1,7 @@
+-- Profile details refreshed from /v1/me on each login, plus a nickname users pick themselves
+ALTER TABLE users ADD COLUMN avatar_url TEXT;
+ALTER TABLE users ADD COLUMN nickname VARCHAR(255);
+ALTER TABLE users ADD COLUMN profile_updated_at TIMESTAMPTZ;
+
+-- Nicknames label users in results, so two users can't share one
+CREATE UNIQUE INDEX users_nickname_key ON users (LOWER(nickname));
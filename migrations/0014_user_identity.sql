@@ -0,0 +1,17 @@
+-- Users were keyed on the JSON-quoted URI from /v1/me, e.g. "spotify:user:abc". Identity is
+-- now the provider plus that provider's user ID, and name is kept as the unquoted URI.
+ALTER TABLE users ADD COLUMN provider VARCHAR(20);
+ALTER TABLE users ADD COLUMN provider_user_id VARCHAR(255);
+
+UPDATE users SET name = TRIM(BOTH '"' FROM name);
+
+UPDATE users
+SET provider = SPLIT_PART(name, ':', 1), provider_user_id = SPLIT_PART(name, ':', 3)
+WHERE name LIKE '%:user:%';
+
+-- Anything else predates Spotify login and keeps its name as its ID
+UPDATE users SET provider = 'legacy', provider_user_id = name WHERE provider IS NULL;
+
+ALTER TABLE users ALTER COLUMN provider SET NOT NULL;
+ALTER TABLE users ALTER COLUMN provider_user_id SET NOT NULL;
+ALTER TABLE users ADD CONSTRAINT users_provider_identity_key UNIQUE (provider, provider_user_id);
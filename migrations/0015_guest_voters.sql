@@ -0,0 +1,17 @@
+-- Voters without a Spotify account join through an invite code and log back in with a PIN
+CREATE EXTENSION IF NOT EXISTS pgcrypto;
+
+CREATE TABLE guest_invites (
+    id SERIAL PRIMARY KEY,
+    code VARCHAR(32) UNIQUE NOT NULL,
+    created_by INT REFERENCES users(id) ON DELETE SET NULL,
+    max_uses INT CHECK (max_uses > 0),
+    uses INT NOT NULL DEFAULT 0,
+    expires_at TIMESTAMPTZ,
+    revoked_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Only set for guests, as a pgcrypto crypt() hash
+ALTER TABLE users ADD COLUMN pin_hash TEXT;
+ALTER TABLE users ADD COLUMN invite_id INT REFERENCES guest_invites(id) ON DELETE SET NULL;
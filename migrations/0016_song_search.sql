@@ -0,0 +1,4 @@
+-- Trigram index for the local catalogue search, matching the expression search_catalogue uses
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE INDEX songs_search_trgm ON songs USING GIN ((name || ' ' || artist) gin_trgm_ops);
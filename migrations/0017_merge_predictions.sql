@@ -0,0 +1,4 @@
+-- Merges move predictions as well as votes, and keep what they moved or dropped for undo
+ALTER TABLE song_merges
+    ADD COLUMN moved_predictions JSONB NOT NULL DEFAULT '[]',
+    ADD COLUMN dropped_predictions JSONB NOT NULL DEFAULT '[]';
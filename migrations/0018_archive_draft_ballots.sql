@@ -0,0 +1,2 @@
+-- Closing a poll archives draft ballots too. Only submitted ones counted towards the results.
+ALTER TABLE poll_rankings ADD COLUMN submitted BOOLEAN NOT NULL DEFAULT TRUE;
@@ -0,0 +1,3 @@
+-- Wrong PINs in a row since the guest last logged in, and when a lockout they earned ends
+ALTER TABLE users ADD COLUMN failed_pin_attempts INT NOT NULL DEFAULT 0;
+ALTER TABLE users ADD COLUMN pin_locked_until TIMESTAMPTZ;
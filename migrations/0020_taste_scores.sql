@@ -0,0 +1,46 @@
+-- How alike two voters' submitted ballots are, one row per pair with user1_id < user2_id. The
+-- taste overview, the taste graph and clusters, and recommendations all score from this.
+CREATE VIEW taste_scores AS
+WITH song_overlap AS (
+    SELECT
+        r1.user_id AS user1_id,
+        r2.user_id AS user2_id,
+        COUNT(*) AS overlapping_songs,
+        AVG(ABS(r1.rank - r2.rank)) AS avg_rank_difference,
+        COUNT(*) * 10.0 - AVG(ABS(r1.rank - r2.rank)) AS song_relationship_strength
+    FROM submitted_rankings r1
+    JOIN submitted_rankings r2
+        ON r1.song_id = r2.song_id
+        AND r1.user_id < r2.user_id
+    GROUP BY r1.user_id, r2.user_id
+),
+artist_overlap AS (
+    SELECT
+        r1.user_id AS user1_id,
+        r2.user_id AS user2_id,
+        COUNT(DISTINCT sa1.artist_id) AS shared_artists,
+        COUNT(*) AS total_artist_overlaps,
+        AVG(ABS(r1.rank - r2.rank)) AS avg_artist_rank_diff
+    FROM submitted_rankings r1
+    JOIN submitted_rankings r2 ON r1.user_id < r2.user_id
+    JOIN song_artists sa1 ON r1.song_id = sa1.song_id
+    JOIN song_artists sa2 ON r2.song_id = sa2.song_id
+    WHERE sa1.artist_id = sa2.artist_id
+    GROUP BY r1.user_id, r2.user_id
+)
+SELECT
+    COALESCE(so.user1_id, ao.user1_id) AS user1_id,
+    COALESCE(so.user2_id, ao.user2_id) AS user2_id,
+    COALESCE(so.overlapping_songs, 0) AS overlapping_songs,
+    COALESCE(so.avg_rank_difference, 0) AS avg_song_rank_diff,
+    COALESCE(so.song_relationship_strength, 0) AS song_strength,
+    COALESCE(ao.shared_artists, 0) AS shared_artists,
+    COALESCE(ao.total_artist_overlaps, 0) AS artist_song_overlaps,
+    COALESCE(ao.avg_artist_rank_diff, 0) AS avg_artist_rank_diff,
+    COALESCE(so.song_relationship_strength, 0) +
+    (COALESCE(ao.shared_artists, 0) * 3.0) -
+    COALESCE(ao.avg_artist_rank_diff, 0) * 0.5 AS combined_score
+FROM song_overlap so
+FULL OUTER JOIN artist_overlap ao
+    ON so.user1_id = ao.user1_id
+    AND so.user2_id = ao.user2_id;
@@ -0,0 +1,73 @@
+-- Submitted ballots archived with a closed poll, mapped to canonical songs like submitted_rankings
+CREATE VIEW poll_submitted_rankings AS
+SELECT DISTINCT ON (pr.poll_id, pr.user_id, COALESCE(s.canonical_id, s.id))
+    pr.poll_id,
+    pr.user_id,
+    COALESCE(s.canonical_id, s.id) AS song_id,
+    pr.rank
+FROM poll_rankings pr
+JOIN songs s ON pr.song_id = s.id
+WHERE pr.submitted
+ORDER BY pr.poll_id, pr.user_id, COALESCE(s.canonical_id, s.id), pr.rank;
+
+-- Taste scores for the open poll's ballots (poll_id NULL) and for every closed poll's archive,
+-- so a poll's wrapped summary scores the same way as the live overview
+DROP VIEW taste_scores;
+
+CREATE VIEW taste_scores AS
+WITH ballots AS (
+    SELECT NULL::INT AS poll_id, user_id, song_id, rank FROM submitted_rankings
+    UNION ALL
+    SELECT poll_id, user_id, song_id, rank FROM poll_submitted_rankings
+),
+song_overlap AS (
+    SELECT
+        r1.poll_id,
+        r1.user_id AS user1_id,
+        r2.user_id AS user2_id,
+        COUNT(*) AS overlapping_songs,
+        AVG(ABS(r1.rank - r2.rank)) AS avg_rank_difference,
+        COUNT(*) * 10.0 - AVG(ABS(r1.rank - r2.rank)) AS song_relationship_strength
+    FROM ballots r1
+    JOIN ballots r2
+        ON r1.poll_id IS NOT DISTINCT FROM r2.poll_id
+        AND r1.song_id = r2.song_id
+        AND r1.user_id < r2.user_id
+    GROUP BY r1.poll_id, r1.user_id, r2.user_id
+),
+artist_overlap AS (
+    SELECT
+        r1.poll_id,
+        r1.user_id AS user1_id,
+        r2.user_id AS user2_id,
+        COUNT(DISTINCT sa1.artist_id) AS shared_artists,
+        COUNT(*) AS total_artist_overlaps,
+        AVG(ABS(r1.rank - r2.rank)) AS avg_artist_rank_diff
+    FROM ballots r1
+    JOIN ballots r2
+        ON r1.poll_id IS NOT DISTINCT FROM r2.poll_id
+        AND r1.user_id < r2.user_id
+    JOIN song_artists sa1 ON r1.song_id = sa1.song_id
+    JOIN song_artists sa2 ON r2.song_id = sa2.song_id
+    WHERE sa1.artist_id = sa2.artist_id
+    GROUP BY r1.poll_id, r1.user_id, r2.user_id
+)
+SELECT
+    CASE WHEN so.user1_id IS NULL THEN ao.poll_id ELSE so.poll_id END AS poll_id,
+    COALESCE(so.user1_id, ao.user1_id) AS user1_id,
+    COALESCE(so.user2_id, ao.user2_id) AS user2_id,
+    COALESCE(so.overlapping_songs, 0) AS overlapping_songs,
+    COALESCE(so.avg_rank_difference, 0) AS avg_song_rank_diff,
+    COALESCE(so.song_relationship_strength, 0) AS song_strength,
+    COALESCE(ao.shared_artists, 0) AS shared_artists,
+    COALESCE(ao.total_artist_overlaps, 0) AS artist_song_overlaps,
+    COALESCE(ao.avg_artist_rank_diff, 0) AS avg_artist_rank_diff,
+    COALESCE(so.song_relationship_strength, 0) +
+    (COALESCE(ao.shared_artists, 0) * 3.0) -
+    COALESCE(ao.avg_artist_rank_diff, 0) * 0.5 AS combined_score
+FROM song_overlap so
+-- Closed poll IDs are positive, so 0 stands in for the open poll in the join
+FULL OUTER JOIN artist_overlap ao
+    ON COALESCE(so.poll_id, 0) = COALESCE(ao.poll_id, 0)
+    AND so.user1_id = ao.user1_id
+    AND so.user2_id = ao.user2_id;
@@ -0,0 +1,261 @@
+use crate::api::db::{RankedVote, TastePair};
+use crate::api::types::{ClusterArtist, ClusterSong, TasteCluster};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+// Two groups are only merged while their average pairwise combined_score stays above this
+pub const DEFAULT_CLUSTER_MIN_SCORE: f64 = 10.0;
+
+const LABEL_SIZE: usize = 5;
+
+// A #1 pick is worth 10 points and a #10 pick is worth 1, the same weighting as the countdown
+pub fn rank_points(rank: i32) -> i64 {
+    (11 - rank).max(0) as i64
+}
+
+struct Similarity {
+    scores: HashMap<(i32, i32), f64>,
+}
+
+impl Similarity {
+    fn new(pairs: &[TastePair]) -> Self {
+        let scores = pairs
+            .iter()
+            .map(|pair| (Self::key(pair.user1_id, pair.user2_id), pair.combined_score))
+            .collect();
+        Similarity { scores }
+    }
+
+    fn key(a: i32, b: i32) -> (i32, i32) {
+        if a < b {
+            (a, b)
+        } else {
+            (b, a)
+        }
+    }
+
+    // Voters with nothing in common have no row in the pair query, so they score 0
+    fn between(&self, a: i32, b: i32) -> f64 {
+        self.scores.get(&Self::key(a, b)).copied().unwrap_or(0.0)
+    }
+
+    fn average_linkage(&self, left: &[i32], right: &[i32]) -> f64 {
+        let total: f64 = left
+            .iter()
+            .flat_map(|a| right.iter().map(move |b| (*a, *b)))
+            .map(|(a, b)| self.between(a, b))
+            .sum();
+        total / (left.len() * right.len()) as f64
+    }
+
+    fn cohesion(&self, members: &[i32]) -> Option<f64> {
+        if members.len() < 2 {
+            return None;
+        }
+        let mut total = 0.0;
+        let mut count = 0;
+        for (i, a) in members.iter().enumerate() {
+            for b in &members[i + 1..] {
+                total += self.between(*a, *b);
+                count += 1;
+            }
+        }
+        Some((total / count as f64 * 100.0).round() / 100.0)
+    }
+}
+
+/// Groups voters into taste "tribes" with average-linkage agglomerative clustering over the
+/// pairwise combined_score, then labels each group with the songs and artists its members
+/// ranked most often.
+pub fn cluster_voters(
+    pairs: &[TastePair],
+    votes: &[RankedVote],
+    min_score: f64,
+) -> Vec<TasteCluster> {
+    let mut names: BTreeMap<i32, String> = BTreeMap::new();
+    for vote in votes {
+        names
+            .entry(vote.user_id)
+            .or_insert_with(|| vote.user_name.clone());
+    }
+    for pair in pairs {
+        names
+            .entry(pair.user1_id)
+            .or_insert_with(|| pair.user1_name.clone());
+        names
+            .entry(pair.user2_id)
+            .or_insert_with(|| pair.user2_name.clone());
+    }
+
+    let similarity = Similarity::new(pairs);
+    let mut groups: Vec<Vec<i32>> = names.keys().map(|id| vec![*id]).collect();
+
+    loop {
+        let mut best: Option<(usize, usize, f64)> = None;
+        for i in 0..groups.len() {
+            for j in i + 1..groups.len() {
+                let score = similarity.average_linkage(&groups[i], &groups[j]);
+                if best.is_none_or(|(_, _, best_score)| score > best_score) {
+                    best = Some((i, j, score));
+                }
+            }
+        }
+
+        match best {
+            Some((i, j, score)) if score >= min_score => {
+                let merged = groups.remove(j);
+                groups[i].extend(merged);
+                groups[i].sort_unstable();
+            }
+            _ => break,
+        }
+    }
+
+    let mut clusters: Vec<TasteCluster> = groups
+        .into_iter()
+        .map(|members| {
+            let member_set: BTreeSet<i32> = members.iter().copied().collect();
+            let member_votes: Vec<&RankedVote> = votes
+                .iter()
+                .filter(|vote| member_set.contains(&vote.user_id))
+                .collect();
+
+            TasteCluster {
+                id: 0,
+                cohesion: similarity.cohesion(&members),
+                members: members.iter().map(|id| names[id].clone()).collect(),
+                defining_songs: defining_songs(&member_votes),
+                defining_artists: defining_artists(&member_votes),
+            }
+        })
+        .collect();
+
+    clusters.sort_by(|a, b| {
+        b.members.len().cmp(&a.members.len()).then(
+            b.cohesion
+                .unwrap_or(0.0)
+                .total_cmp(&a.cohesion.unwrap_or(0.0)),
+        )
+    });
+    for (index, cluster) in clusters.iter_mut().enumerate() {
+        cluster.id = index + 1;
+    }
+
+    clusters
+}
+
+fn defining_songs(votes: &[&RankedVote]) -> Vec<ClusterSong> {
+    let mut tally: HashMap<i32, ClusterSong> = HashMap::new();
+    for vote in votes {
+        let entry = tally.entry(vote.song_id).or_insert_with(|| ClusterSong {
+            name: vote.song_name.clone(),
+            artist: vote.artist.clone(),
+            votes: 0,
+            points: 0,
+        });
+        entry.votes += 1;
+        entry.points += rank_points(vote.rank);
+    }
+
+    let mut songs: Vec<ClusterSong> = tally.into_values().collect();
+    songs.sort_by(|a, b| {
+        b.votes
+            .cmp(&a.votes)
+            .then(b.points.cmp(&a.points))
+            .then(a.name.cmp(&b.name))
+    });
+    songs.truncate(LABEL_SIZE);
+    songs
+}
+
+fn defining_artists(votes: &[&RankedVote]) -> Vec<ClusterArtist> {
+    let mut voters: HashMap<&str, BTreeSet<i32>> = HashMap::new();
+    let mut points: HashMap<&str, i64> = HashMap::new();
+    for vote in votes {
+        // Features count for every credited artist, not just the first listed
+        for artist in &vote.artists {
+            voters.entry(artist).or_default().insert(vote.user_id);
+            *points.entry(artist).or_default() += rank_points(vote.rank);
+        }
+    }
+
+    let mut artists: Vec<ClusterArtist> = voters
+        .into_iter()
+        .map(|(artist, users)| ClusterArtist {
+            artist: artist.to_string(),
+            votes: users.len() as i64,
+            points: points[artist],
+        })
+        .collect();
+    artists.sort_by(|a, b| {
+        b.votes
+            .cmp(&a.votes)
+            .then(b.points.cmp(&a.points))
+            .then(a.artist.cmp(&b.artist))
+    });
+    artists.truncate(LABEL_SIZE);
+    artists
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair(user1_id: i32, user2_id: i32, combined_score: f64) -> TastePair {
+        TastePair {
+            user1_id,
+            user1_name: format!("user {}", user1_id),
+            user2_id,
+            user2_name: format!("user {}", user2_id),
+            combined_score,
+        }
+    }
+
+    fn vote(user_id: i32, song_id: i32, artists: &[&str], rank: i32) -> RankedVote {
+        RankedVote {
+            user_id,
+            user_name: format!("user {}", user_id),
+            song_id,
+            song_name: format!("Song {}", song_id),
+            artist: artists[0].to_string(),
+            artists: artists.iter().map(|artist| artist.to_string()).collect(),
+            uri: format!("spotify:track:{}", song_id),
+            album_cover_url: String::new(),
+            rank,
+        }
+    }
+
+    #[test]
+    fn rank_points_run_from_10_down_to_0() {
+        assert_eq!(rank_points(1), 10);
+        assert_eq!(rank_points(10), 1);
+        assert_eq!(rank_points(11), 0);
+    }
+
+    #[test]
+    fn close_voters_are_grouped_and_labelled() {
+        let pairs = [pair(1, 2, 20.0), pair(1, 3, 1.0), pair(2, 3, 3.0)];
+        let votes = [
+            vote(1, 10, &["Flume"], 1),
+            vote(2, 10, &["Flume"], 2),
+            vote(2, 11, &["Lorde", "Flume"], 1),
+            vote(3, 12, &["Tame Impala"], 1),
+        ];
+
+        let clusters = cluster_voters(&pairs, &votes, DEFAULT_CLUSTER_MIN_SCORE);
+        assert_eq!(clusters.len(), 2);
+
+        let tribe = &clusters[0];
+        assert_eq!(tribe.id, 1);
+        assert_eq!(tribe.members, ["user 1", "user 2"]);
+        assert_eq!(tribe.cohesion, Some(20.0));
+        assert_eq!(tribe.defining_songs[0].name, "Song 10");
+        assert_eq!(tribe.defining_songs[0].votes, 2);
+        assert_eq!(tribe.defining_songs[0].points, 19);
+        // The feature on song 11 counts towards Flume as well
+        assert_eq!(tribe.defining_artists[0].artist, "Flume");
+        assert_eq!(tribe.defining_artists[0].points, 29);
+
+        assert_eq!(clusters[1].members, ["user 3"]);
+        assert_eq!(clusters[1].cohesion, None);
+    }
+}
@@ -0,0 +1,134 @@
+use crate::api::types::Song;
+use rocket::http::{Header, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+
+/// The ballot version from an `If-Match` header. Missing or `*` matches any version.
+pub struct IfMatch(pub Option<i32>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IfMatch {
+    type Error = String;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match request.headers().get_one("If-Match").map(str::trim) {
+            None | Some("*") => Outcome::Success(IfMatch(None)),
+            Some(etag) => {
+                let version = etag.trim_start_matches("W/").trim_matches('"');
+                match version.parse() {
+                    Ok(version) => Outcome::Success(IfMatch(Some(version))),
+                    Err(_) => Outcome::Error((
+                        Status::BadRequest,
+                        format!("Unrecognised If-Match {}", etag),
+                    )),
+                }
+            }
+        }
+    }
+}
+
+/// A response carrying the ballot version it reflects as its `ETag`
+#[derive(Responder)]
+pub struct Versioned<R> {
+    inner: R,
+    etag: Header<'static>,
+}
+
+impl<R> Versioned<R> {
+    pub fn new(inner: R, version: i32) -> Self {
+        Versioned {
+            inner,
+            etag: Header::new("ETag", format!("\"{}\"", version)),
+        }
+    }
+}
+
+/// Removes the song at `rank` and moves everything below it up one. Returns false if nothing
+/// is at that rank.
+pub fn remove_entry(songs: &mut Vec<Song>, rank: i32) -> bool {
+    let Some(index) = songs.iter().position(|song| song.rank == Some(rank)) else {
+        return false;
+    };
+    songs.remove(index);
+    for song in songs.iter_mut() {
+        if let Some(other) = song.rank.filter(|other| *other > rank) {
+            song.rank = Some(other - 1);
+        }
+    }
+    true
+}
+
+/// Moves the song at `from` to `to`, shifting the songs in between by one towards the gap it
+/// left. A `to` past the end of the ballot moves the song to the bottom, so no gap opens up.
+/// Returns false if nothing is at `from`.
+pub fn move_entry(songs: &mut [Song], from: i32, to: i32) -> bool {
+    if !songs.iter().any(|song| song.rank == Some(from)) {
+        return false;
+    }
+    let to = to.clamp(1, songs.len() as i32);
+    for song in songs.iter_mut() {
+        song.rank = song.rank.map(|rank| match rank {
+            rank if rank == from => to,
+            rank if from < to && rank > from && rank <= to => rank - 1,
+            rank if to < from && rank >= to && rank < from => rank + 1,
+            rank => rank,
+        });
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::types::TrackMetadata;
+
+    fn ballot(uris: &[&str]) -> Vec<Song> {
+        uris.iter()
+            .zip(1..)
+            .map(|(uri, rank)| Song {
+                key: None,
+                name: uri.to_string(),
+                uri: uri.to_string(),
+                artist: "Artist".to_string(),
+                album_cover_url: String::new(),
+                release_date: None,
+                release_date_precision: None,
+                metadata: TrackMetadata::default(),
+                rank: Some(rank),
+            })
+            .collect()
+    }
+
+    fn in_order(songs: &[Song]) -> Vec<&str> {
+        let mut songs: Vec<&Song> = songs.iter().collect();
+        songs.sort_by_key(|song| song.rank);
+        songs.iter().map(|song| song.uri.as_str()).collect()
+    }
+
+    #[test]
+    fn removing_an_entry_closes_the_gap() {
+        let mut songs = ballot(&["a", "b", "c"]);
+        assert!(remove_entry(&mut songs, 2));
+        assert_eq!(in_order(&songs), ["a", "c"]);
+        assert_eq!(songs[1].rank, Some(2));
+        assert!(!remove_entry(&mut songs, 3));
+    }
+
+    #[test]
+    fn moving_an_entry_shifts_the_songs_in_between() {
+        let mut songs = ballot(&["a", "b", "c", "d"]);
+        assert!(move_entry(&mut songs, 1, 3));
+        assert_eq!(in_order(&songs), ["b", "c", "a", "d"]);
+        assert!(move_entry(&mut songs, 4, 1));
+        assert_eq!(in_order(&songs), ["d", "b", "c", "a"]);
+        assert!(!move_entry(&mut songs, 5, 1));
+    }
+
+    #[test]
+    fn moving_past_the_end_lands_at_the_bottom() {
+        let mut songs = ballot(&["a", "b", "c"]);
+        assert!(move_entry(&mut songs, 1, 10));
+        assert_eq!(in_order(&songs), ["b", "c", "a"]);
+        let ranks: Vec<Option<i32>> = songs.iter().map(|song| song.rank).collect();
+        assert_eq!(ranks, [Some(3), Some(1), Some(2)]);
+    }
+}
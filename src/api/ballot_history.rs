@@ -0,0 +1,91 @@
+use crate::api::types::{BallotChange, BallotChangeKind, RevisionEntry};
+use std::collections::HashMap;
+
+/// Lists what changed between two revisions of a ballot, matching songs on URI. Changes come
+/// in the order of the rank they now hold (or held, for removed songs), so a changed #1 is
+/// always first.
+pub fn diff_revisions(from: &[RevisionEntry], to: &[RevisionEntry]) -> Vec<BallotChange> {
+    let before: HashMap<&str, &RevisionEntry> =
+        from.iter().map(|entry| (entry.uri.as_str(), entry)).collect();
+    let after: HashMap<&str, &RevisionEntry> =
+        to.iter().map(|entry| (entry.uri.as_str(), entry)).collect();
+
+    let change = |kind, entry: &RevisionEntry, from_rank, to_rank| BallotChange {
+        kind,
+        uri: entry.uri.clone(),
+        name: entry.name.clone(),
+        artist: entry.artist.clone(),
+        from_rank,
+        to_rank,
+    };
+
+    let mut changes = Vec::new();
+    for entry in to {
+        match before.get(entry.uri.as_str()) {
+            None => changes.push(change(BallotChangeKind::Added, entry, None, Some(entry.rank))),
+            Some(old) if old.rank != entry.rank => changes.push(change(
+                BallotChangeKind::Moved,
+                entry,
+                Some(old.rank),
+                Some(entry.rank),
+            )),
+            Some(_) => {}
+        }
+    }
+    for entry in from {
+        if !after.contains_key(entry.uri.as_str()) {
+            changes.push(change(BallotChangeKind::Removed, entry, Some(entry.rank), None));
+        }
+    }
+
+    changes.sort_by_key(|change| change.to_rank.or(change.from_rank));
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(uri: &str, rank: i32) -> RevisionEntry {
+        RevisionEntry {
+            rank,
+            uri: uri.to_string(),
+            name: format!("Song {}", uri),
+            artist: "Artist".to_string(),
+        }
+    }
+
+    #[test]
+    fn changes_come_in_rank_order() {
+        let from = [entry("a", 1), entry("b", 2), entry("c", 3), entry("e", 4)];
+        let to = [entry("b", 1), entry("a", 2), entry("d", 3), entry("e", 4)];
+
+        let diff = diff_revisions(&from, &to);
+        let summary: Vec<_> = diff
+            .iter()
+            .map(|change| {
+                (
+                    &change.kind,
+                    change.uri.as_str(),
+                    change.from_rank,
+                    change.to_rank,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (&BallotChangeKind::Moved, "b", Some(2), Some(1)),
+                (&BallotChangeKind::Moved, "a", Some(1), Some(2)),
+                (&BallotChangeKind::Added, "d", None, Some(3)),
+                (&BallotChangeKind::Removed, "c", Some(3), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn identical_revisions_have_no_changes() {
+        let songs = [entry("a", 1), entry("b", 2)];
+        assert!(diff_revisions(&songs, &songs).is_empty());
+    }
+}
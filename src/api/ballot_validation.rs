@@ -0,0 +1,274 @@
+use crate::api::eligibility::{self, Eligibility};
+use crate::api::types::{BallotError, BallotRules, EligibilityFlag, Poll, Song};
+use std::collections::{HashMap, HashSet};
+
+// Matches the CHECK constraint on rankings.rank
+pub const MAX_RANK: i32 = 10;
+
+pub struct BallotCheck {
+    pub errors: Vec<BallotError>,
+    pub flagged: Vec<EligibilityFlag>,
+}
+
+fn row_error(row: usize, song: &Song, rule: &str, message: String) -> BallotError {
+    BallotError {
+        row: Some(row),
+        rank: song.rank,
+        uri: Some(song.uri.clone()),
+        rule: rule.to_string(),
+        message,
+    }
+}
+
+/// Checks a submitted ballot against the poll's rules. Every broken rule is reported against
+/// the row (index into the submitted list) that broke it, so the client can highlight them all
+/// at once. Rules about the ballot as a whole have no row. Songs whose release date straddles
+/// an edge of the window are never errors and come back in `flagged` instead.
+pub fn validate_ballot(
+    songs: &[Song],
+    rules: &BallotRules,
+    poll: Option<(&Poll, &HashSet<String>)>,
+) -> BallotCheck {
+    let mut errors = Vec::new();
+    let mut flagged = Vec::new();
+
+    if songs.len() < rules.min_songs || songs.len() > rules.max_songs {
+        errors.push(BallotError {
+            row: None,
+            rank: None,
+            uri: None,
+            rule: "ballot_length".to_string(),
+            message: format!(
+                "Ballots need between {} and {} songs, this one has {}",
+                rules.min_songs,
+                rules.max_songs,
+                songs.len()
+            ),
+        });
+    }
+
+    let mut seen_ranks: HashMap<i32, usize> = HashMap::new();
+    let mut seen_uris: HashMap<&str, usize> = HashMap::new();
+    let mut per_artist: HashMap<String, usize> = HashMap::new();
+
+    for (row, song) in songs.iter().enumerate() {
+        match song.rank {
+            None => errors.push(row_error(row, song, "rank", "Missing rank".to_string())),
+            Some(rank) if !(1..=MAX_RANK).contains(&rank) => errors.push(row_error(
+                row,
+                song,
+                "rank",
+                format!("Rank {} is outside 1 to {}", rank, MAX_RANK),
+            )),
+            Some(rank) => {
+                if let Some(first) = seen_ranks.insert(rank, row) {
+                    if rules.unique_ranks {
+                        errors.push(row_error(
+                            row,
+                            song,
+                            "unique_ranks",
+                            format!("Rank {} is already used by row {}", rank, first),
+                        ));
+                    }
+                }
+            }
+        }
+
+        if let Some(first) = seen_uris.insert(song.uri.as_str(), row) {
+            if rules.unique_songs {
+                errors.push(row_error(
+                    row,
+                    song,
+                    "unique_songs",
+                    format!("{} is already on this ballot at row {}", song.name, first),
+                ));
+            }
+        }
+
+        let count = per_artist.entry(song.artist.to_lowercase()).or_default();
+        *count += 1;
+        if let Some(cap) = rules.max_per_artist {
+            if *count > cap {
+                errors.push(row_error(
+                    row,
+                    song,
+                    "max_per_artist",
+                    format!("More than {} songs by {}", cap, song.artist),
+                ));
+            }
+        }
+
+        if let Some((poll, overrides)) = poll {
+            let flag = |reason: String| EligibilityFlag {
+                name: song.name.clone(),
+                artist: song.artist.clone(),
+                rank: song.rank,
+                reason,
+            };
+            match eligibility::check_song(song, poll, overrides) {
+                Eligibility::Eligible => {}
+                Eligibility::Flagged(reason) => flagged.push(flag(reason)),
+                Eligibility::Ineligible(reason) if rules.eligible_only => {
+                    errors.push(row_error(row, song, "eligible_only", reason))
+                }
+                Eligibility::Ineligible(reason) => flagged.push(flag(reason)),
+            }
+        }
+    }
+
+    if rules.contiguous_ranks {
+        let mut ranks: Vec<i32> = seen_ranks.keys().copied().collect();
+        ranks.sort();
+        if let Some(missing) = (1..=ranks.len() as i32).find(|rank| ranks.binary_search(rank).is_err()) {
+            errors.push(BallotError {
+                row: None,
+                rank: Some(missing),
+                uri: None,
+                rule: "contiguous_ranks".to_string(),
+                message: format!("Ranks must run from 1 with no gaps, {} is missing", missing),
+            });
+        }
+    }
+
+    BallotCheck { errors, flagged }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::types::TrackMetadata;
+    use chrono::{NaiveDate, Utc};
+
+    fn song(rank: Option<i32>, uri: &str, artist: &str) -> Song {
+        Song {
+            key: None,
+            name: format!("Song {}", uri),
+            uri: format!("spotify:track:{}", uri),
+            artist: artist.to_string(),
+            album_cover_url: String::new(),
+            release_date: Some("2024-06-01".to_string()),
+            release_date_precision: Some("day".to_string()),
+            metadata: TrackMetadata::default(),
+            rank,
+        }
+    }
+
+    fn poll_for_2024() -> Poll {
+        Poll {
+            id: 1,
+            name: "2024".to_string(),
+            created_at: Utc::now(),
+            closed_at: None,
+            eligible_from: NaiveDate::from_ymd_opt(2023, 12, 1),
+            eligible_to: NaiveDate::from_ymd_opt(2024, 11, 30),
+        }
+    }
+
+    fn broken_rules(check: &BallotCheck) -> Vec<(Option<usize>, &str)> {
+        check
+            .errors
+            .iter()
+            .map(|error| (error.row, error.rule.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn a_good_ballot_passes() {
+        let songs = [song(Some(1), "a", "Flume"), song(Some(2), "b", "Lorde")];
+        let check = validate_ballot(&songs, &BallotRules::default(), None);
+        assert!(check.errors.is_empty());
+        assert!(check.flagged.is_empty());
+    }
+
+    #[test]
+    fn every_broken_row_is_reported() {
+        let songs = [
+            song(None, "a", "Flume"),
+            song(Some(0), "b", "Lorde"),
+            song(Some(MAX_RANK + 1), "c", "Lorde"),
+            song(Some(4), "d", "Flume"),
+            song(Some(4), "e", "Flume"),
+            song(Some(5), "d", "Lorde"),
+        ];
+        let check = validate_ballot(&songs, &BallotRules::default(), None);
+        assert_eq!(
+            broken_rules(&check),
+            [
+                (Some(0), "rank"),
+                (Some(1), "rank"),
+                (Some(2), "rank"),
+                (Some(4), "unique_ranks"),
+                (Some(5), "unique_songs"),
+            ]
+        );
+    }
+
+    #[test]
+    fn ballot_wide_rules_have_no_row() {
+        let rules = BallotRules {
+            min_songs: 3,
+            contiguous_ranks: true,
+            ..BallotRules::default()
+        };
+        let songs = [song(Some(1), "a", "Flume"), song(Some(3), "b", "Lorde")];
+        let check = validate_ballot(&songs, &rules, None);
+        assert_eq!(
+            broken_rules(&check),
+            [(None, "ballot_length"), (None, "contiguous_ranks")]
+        );
+        assert_eq!(check.errors[1].rank, Some(2));
+    }
+
+    #[test]
+    fn artists_are_capped_ignoring_case() {
+        let rules = BallotRules {
+            max_per_artist: Some(1),
+            ..BallotRules::default()
+        };
+        let songs = [song(Some(1), "a", "Flume"), song(Some(2), "b", "FLUME")];
+        let check = validate_ballot(&songs, &rules, None);
+        assert_eq!(broken_rules(&check), [(Some(1), "max_per_artist")]);
+    }
+
+    #[test]
+    fn ineligible_songs_are_errors_or_flags_depending_on_the_rules() {
+        let poll = poll_for_2024();
+        let overrides = HashSet::new();
+        let mut old = song(Some(1), "a", "Flume");
+        old.release_date = Some("2016-01-01".to_string());
+        let mut undated = song(Some(2), "b", "Lorde");
+        undated.release_date = None;
+        let mut straddling = song(Some(3), "c", "Tame Impala");
+        straddling.release_date = Some("2023".to_string());
+        straddling.release_date_precision = Some("year".to_string());
+        let songs = [old, undated, straddling];
+
+        let check = validate_ballot(&songs, &BallotRules::default(), Some((&poll, &overrides)));
+        assert_eq!(
+            broken_rules(&check),
+            [(Some(0), "eligible_only"), (Some(1), "eligible_only")]
+        );
+        assert_eq!(check.flagged.len(), 1);
+        assert_eq!(check.flagged[0].rank, Some(3));
+
+        let rules = BallotRules {
+            eligible_only: false,
+            ..BallotRules::default()
+        };
+        let check = validate_ballot(&songs, &rules, Some((&poll, &overrides)));
+        assert!(check.errors.is_empty());
+        assert_eq!(check.flagged.len(), 3);
+    }
+
+    #[test]
+    fn overrides_make_a_song_eligible() {
+        let poll = poll_for_2024();
+        let mut old = song(Some(1), "a", "Flume");
+        old.release_date = Some("2016-01-01".to_string());
+        let overrides = HashSet::from([old.uri.clone()]);
+
+        let check = validate_ballot(&[old], &BallotRules::default(), Some((&poll, &overrides)));
+        assert!(check.errors.is_empty());
+        assert!(check.flagged.is_empty());
+    }
+}
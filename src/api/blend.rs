@@ -0,0 +1,107 @@
+use crate::api::types::Song;
+use std::collections::{HashMap, HashSet};
+
+/// Interleaves two ballots into a single playlist order (URIs, first track first).
+///
+/// 1. Songs on both ballots come first, ordered by combined rank (`mine + theirs`), ties
+///    broken by my rank.
+/// 2. The remaining songs alternate one pick at a time, mine first, each side in its own
+///    rank order. When one side runs out, the rest of the other side follows in rank order.
+///
+/// Songs are matched on URI, and a URI is never added twice.
+pub fn blend_ballots(mine: &[Song], theirs: &[Song]) -> Vec<String> {
+    let mine = ranked(mine);
+    let theirs = ranked(theirs);
+
+    let their_ranks: HashMap<&str, i32> = theirs
+        .iter()
+        .map(|song| (song.uri.as_str(), song.rank.unwrap_or(i32::MAX)))
+        .collect();
+
+    let mut shared: Vec<(i32, i32, &str)> = mine
+        .iter()
+        .filter_map(|song| {
+            let my_rank = song.rank.unwrap_or(i32::MAX);
+            let their_rank = their_ranks.get(song.uri.as_str())?;
+            Some((
+                my_rank.saturating_add(*their_rank),
+                my_rank,
+                song.uri.as_str(),
+            ))
+        })
+        .collect();
+    shared.sort();
+
+    let mut seen: HashSet<&str> = HashSet::new();
+    let mut playlist: Vec<String> = Vec::new();
+    for (_, _, uri) in shared {
+        if seen.insert(uri) {
+            playlist.push(uri.to_string());
+        }
+    }
+
+    let mut my_rest = mine.iter().filter(|song| !seen.contains(song.uri.as_str()));
+    let mut their_rest = theirs
+        .iter()
+        .filter(|song| !seen.contains(song.uri.as_str()));
+    let mut alternate: Vec<&str> = Vec::new();
+    loop {
+        let my_pick = my_rest.next();
+        let their_pick = their_rest.next();
+        if my_pick.is_none() && their_pick.is_none() {
+            break;
+        }
+        alternate.extend(my_pick.map(|song| song.uri.as_str()));
+        alternate.extend(their_pick.map(|song| song.uri.as_str()));
+    }
+
+    for uri in alternate {
+        if seen.insert(uri) {
+            playlist.push(uri.to_string());
+        }
+    }
+
+    playlist
+}
+
+fn ranked(songs: &[Song]) -> Vec<&Song> {
+    let mut songs: Vec<&Song> = songs.iter().collect();
+    songs.sort_by_key(|song| song.rank.unwrap_or(i32::MAX));
+    songs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::types::TrackMetadata;
+
+    fn song(uri: &str, rank: i32) -> Song {
+        Song {
+            key: None,
+            name: format!("Song {}", uri),
+            uri: uri.to_string(),
+            artist: "Artist".to_string(),
+            album_cover_url: String::new(),
+            release_date: None,
+            release_date_precision: None,
+            metadata: TrackMetadata::default(),
+            rank: Some(rank),
+        }
+    }
+
+    #[test]
+    fn shared_songs_lead_then_picks_alternate_mine_first() {
+        let mine = [song("d", 4), song("a", 1), song("c", 3), song("b", 2)];
+        let theirs = [song("x", 2), song("c", 1), song("a", 3)];
+
+        // a and c both total 4, a wins on my rank. Then b, x, and d once they run out.
+        assert_eq!(blend_ballots(&mine, &theirs), ["a", "c", "b", "x", "d"]);
+    }
+
+    #[test]
+    fn a_song_is_never_added_twice() {
+        let mine = [song("a", 1), song("a", 2)];
+        let theirs = [song("b", 1), song("b", 2)];
+        assert_eq!(blend_ballots(&mine, &theirs), ["a", "b"]);
+    }
+}
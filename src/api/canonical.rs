@@ -0,0 +1,105 @@
+// Bracketed or dashed title suffixes that mark a version of a song rather than a different song
+const VERSION_MARKERS: [&str; 14] = [
+    "feat", "ft", "with", "radio edit", "edit", "remaster", "deluxe", "single", "album version",
+    "original mix", "explicit", "clean", "mono", "stereo",
+];
+
+fn is_version_marker(segment: &str) -> bool {
+    let segment = segment.trim().trim_end_matches('.').to_lowercase();
+    VERSION_MARKERS.iter().any(|marker| {
+        segment == *marker
+            || segment.starts_with(&format!("{} ", marker))
+            || segment.starts_with(&format!("{}.", marker))
+            || segment.ends_with(&format!(" {}", marker))
+            || (marker.len() > 4 && segment.contains(marker))
+    })
+}
+
+/// Normalises a track title so versions of the same song compare equal, e.g. "Song - Radio
+/// Edit", "Song (feat. X)" and "Song [2019 Remaster]" all become "song". Remixes, live
+/// recordings and acoustic versions are kept distinct.
+pub fn normalize_title(name: &str) -> String {
+    let mut title = String::new();
+    let mut bracket: Option<(char, String)> = None;
+    for c in name.chars() {
+        match (&mut bracket, c) {
+            (None, '(' | '[') => bracket = Some((if c == '(' { ')' } else { ']' }, String::new())),
+            (None, _) => title.push(c),
+            (Some((close, inner)), _) if c == *close => {
+                if !is_version_marker(inner) {
+                    title.push(' ');
+                    title.push_str(inner);
+                }
+                bracket = None;
+            }
+            (Some((_, inner)), _) => inner.push(c),
+        }
+    }
+    // An unclosed bracket is just part of the title
+    if let Some((_, inner)) = bracket {
+        title.push(' ');
+        title.push_str(&inner);
+    }
+
+    let mut parts = title.split(" - ");
+    let mut kept = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        if !is_version_marker(part) {
+            kept.push(' ');
+            kept.push_str(part);
+        }
+    }
+
+    kept.to_lowercase()
+        .replace('&', " and ")
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_of_a_song_share_a_title() {
+        for name in [
+            "Never Be Like You",
+            "Never Be Like You - Radio Edit",
+            "Never Be Like You (feat. Kai)",
+            "Never Be Like You [2019 Remaster]",
+            "Never Be Like You (Radio Edit) [Explicit]",
+            "NEVER BE LIKE YOU!",
+            "  Never   Be Like You ",
+        ] {
+            assert_eq!(normalize_title(name), "never be like you", "{}", name);
+        }
+    }
+
+    #[test]
+    fn remixes_and_live_versions_stay_distinct() {
+        assert_eq!(
+            normalize_title("Innerbloom - What So Not Remix"),
+            "innerbloom what so not remix"
+        );
+        assert_eq!(normalize_title("Innerbloom (Live)"), "innerbloom live");
+        assert_eq!(
+            normalize_title("Innerbloom [Acoustic]"),
+            "innerbloom acoustic"
+        );
+    }
+
+    #[test]
+    fn punctuation_and_ampersands_are_normalised() {
+        assert_eq!(normalize_title("Salt & Vinegar"), "salt and vinegar");
+        assert_eq!(normalize_title("Don't Stop"), "don t stop");
+    }
+
+    #[test]
+    fn an_unclosed_bracket_is_part_of_the_title() {
+        assert_eq!(normalize_title("Song (Part 2"), "song part 2");
+    }
+}
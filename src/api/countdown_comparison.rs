@@ -0,0 +1,181 @@
+use crate::api::db::CountdownSong;
+use crate::api::types::{ComparedSong, CountdownComparison, OfficialResultEntry};
+use std::collections::HashMap;
+
+const LIST_SIZE: usize = 10;
+
+/// Reads an official countdown CSV with a header row. `position` is required, and each row is
+/// keyed by `uri` when present, otherwise by `name` and `artist`.
+pub fn parse_official_results_csv(body: &str) -> Result<Vec<OfficialResultEntry>, csv::Error> {
+    csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(body.as_bytes())
+        .deserialize()
+        .map(|row| {
+            row.map(|mut entry: OfficialResultEntry| {
+                entry.uri = entry.uri.filter(|uri| !uri.is_empty());
+                entry
+            })
+        })
+        .collect()
+}
+
+/// Compares our group's countdown with the official one.
+///
+/// - `biggest_agreements` are songs in both lists, closest positions first.
+/// - `loved_but_missed` are our highest placed songs that aren't in the official list.
+/// - `rank_correlation` is Spearman's rho over the songs in both lists, each re-ranked within
+///   that overlap, and is only given when at least two songs overlap.
+pub fn compare_countdowns(
+    poll: &str,
+    group: &[CountdownSong],
+    official: &[CountdownSong],
+) -> CountdownComparison {
+    let official_positions: HashMap<&str, i32> = official
+        .iter()
+        .map(|song| (song.uri.as_str(), song.position))
+        .collect();
+
+    let mut group_sorted: Vec<&CountdownSong> = group.iter().collect();
+    group_sorted.sort_by_key(|song| song.position);
+
+    let mut shared: Vec<ComparedSong> = Vec::new();
+    let mut loved_but_missed: Vec<ComparedSong> = Vec::new();
+    for song in group_sorted {
+        let compared = ComparedSong {
+            name: song.name.clone(),
+            artist: song.artist.clone(),
+            group_position: Some(song.position),
+            official_position: official_positions.get(song.uri.as_str()).copied(),
+        };
+        if compared.official_position.is_some() {
+            shared.push(compared);
+        } else {
+            loved_but_missed.push(compared);
+        }
+    }
+
+    let rank_correlation = spearman(&shared);
+
+    let overlap = shared.len();
+    let mut biggest_agreements = shared;
+    biggest_agreements.sort_by_key(|song| {
+        (
+            (song.group_position.unwrap_or_default() - song.official_position.unwrap_or_default())
+                .abs(),
+            song.official_position,
+        )
+    });
+    biggest_agreements.truncate(LIST_SIZE);
+    loved_but_missed.truncate(LIST_SIZE);
+
+    CountdownComparison {
+        poll: poll.to_string(),
+        group_size: group.len(),
+        official_size: official.len(),
+        overlap,
+        biggest_agreements,
+        loved_but_missed,
+        rank_correlation,
+    }
+}
+
+// `shared` must be sorted by group position
+fn spearman(shared: &[ComparedSong]) -> Option<f64> {
+    let n = shared.len();
+    if n < 2 {
+        return None;
+    }
+
+    let mut by_official: Vec<usize> = (0..n).collect();
+    by_official.sort_by_key(|index| shared[*index].official_position);
+    let mut official_rank = vec![0usize; n];
+    for (rank, index) in by_official.into_iter().enumerate() {
+        official_rank[index] = rank;
+    }
+
+    let sum_squared: f64 = official_rank
+        .iter()
+        .enumerate()
+        .map(|(group_rank, official_rank)| (group_rank as f64 - *official_rank as f64).powi(2))
+        .sum();
+    let n = n as f64;
+    let rho = 1.0 - 6.0 * sum_squared / (n * (n * n - 1.0));
+
+    Some((rho * 1000.0).round() / 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn countdown(uris: &[&str]) -> Vec<CountdownSong> {
+        uris.iter()
+            .enumerate()
+            .map(|(index, uri)| CountdownSong {
+                uri: uri.to_string(),
+                name: format!("Song {}", uri),
+                artist: "Artist".to_string(),
+                position: index as i32 + 1,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn identical_countdowns_correlate_perfectly() {
+        let songs = countdown(&["a", "b", "c", "d"]);
+        let comparison = compare_countdowns("2024", &songs, &songs);
+        assert_eq!(comparison.overlap, 4);
+        assert_eq!(comparison.rank_correlation, Some(1.0));
+        assert!(comparison.loved_but_missed.is_empty());
+    }
+
+    #[test]
+    fn reversed_countdowns_correlate_negatively() {
+        let group = countdown(&["a", "b", "c", "d"]);
+        let official = countdown(&["d", "c", "b", "a"]);
+        let comparison = compare_countdowns("2024", &group, &official);
+        assert_eq!(comparison.rank_correlation, Some(-1.0));
+    }
+
+    #[test]
+    fn correlation_reranks_within_the_overlap() {
+        // Official positions 1, 5 and 9 rank the same as 1, 2 and 3 once re-ranked
+        let group = countdown(&["a", "b", "c", "x"]);
+        let official = countdown(&["a", "y", "y", "y", "b", "y", "y", "y", "c"]);
+        let comparison = compare_countdowns("2024", &group, &official);
+        assert_eq!(comparison.overlap, 3);
+        assert_eq!(comparison.rank_correlation, Some(1.0));
+        assert_eq!(comparison.loved_but_missed.len(), 1);
+        assert_eq!(comparison.loved_but_missed[0].name, "Song x");
+    }
+
+    #[test]
+    fn a_single_shared_song_has_no_correlation() {
+        let group = countdown(&["a", "b"]);
+        let official = countdown(&["a", "c"]);
+        let comparison = compare_countdowns("2024", &group, &official);
+        assert_eq!(comparison.rank_correlation, None);
+    }
+
+    #[test]
+    fn one_swap_in_five() {
+        // d^2 sums to 2, so rho = 1 - 6 * 2 / (5 * 24) = 0.9
+        let group = countdown(&["a", "b", "c", "d", "e"]);
+        let official = countdown(&["b", "a", "c", "d", "e"]);
+        let comparison = compare_countdowns("2024", &group, &official);
+        assert_eq!(comparison.rank_correlation, Some(0.9));
+        assert_eq!(comparison.biggest_agreements[0].name, "Song c");
+    }
+
+    #[test]
+    fn csv_rows_key_on_uri_or_name_and_artist() {
+        let results = parse_official_results_csv(
+            "position,uri,name,artist\n1, spotify:track:a ,,\n2,,Song B,Artist\n",
+        )
+        .unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].uri.as_deref(), Some("spotify:track:a"));
+        assert_eq!(results[1].uri, None);
+    }
+}
@@ -1,149 +1,743 @@
-use crate::api::types::{MusicTasteOverview, Song};
+use crate::api::canonical::normalize_title;
+use crate::api::session::SessionUser;
+use crate::api::types::{
+    BallotRevision, BallotRules, BallotStatus, BallotSubmissions, CanonicalScan, GuestInvite,
+    GuestLogin, MergeCandidate, MergeSuggestion, MergedPrediction, MergedRanking, MigrationStatus,
+    MusicTasteOverview, Poll, RevisionEntry, Song, SongMerge, SongMergeUndo, TrackArtist,
+    TrackImage, TrackMetadata, UserProfile,
+};
+use crate::DB_POOL;
+use chrono::{DateTime, NaiveDate, Utc};
+use sqlx::migrate::{Migrate, MigrateError, Migrator};
+use sqlx::types::Json;
 use sqlx::{FromRow, Transaction};
 use sqlx_postgres::{PgPool, Postgres};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::option::Option;
 
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// The shared pool. It is only missing if it failed to start, which stops launch, but a
+/// request still gets an error rather than a panic.
+pub fn pool() -> Result<&'static PgPool, sqlx::Error> {
+    DB_POOL.get().ok_or(sqlx::Error::PoolClosed)
+}
+
 #[derive(FromRow)]
 pub struct User {
     pub id: i32,
     pub name: String,
+    // The nickname, else the Spotify display name, else the raw name
+    pub label: String,
 }
 
+// Another voter scored against the active user, see get_music_taste_user
 #[derive(FromRow)]
 pub struct MusicTasteIndividual {
+    pub other_user_id: i32,
     pub other_user_name: String,
-    pub overlapping_songs: Option<i64>,
-    pub song_rank_diff: Option<f64>,
-    pub song_relationship_strength: Option<f64>,
-    pub overlapping_artists: Option<i64>,
-    pub total_songs_shared_artists: Option<i64>,
-    pub artist_rank_diff: Option<f64>,
-    pub combined_score: Option<f64>,
-    pub overlapping_song_details: Option<serde_json::Value>,
-    pub overlapping_artist_details: Option<serde_json::Value>,
-}
-
-pub async fn get_or_insert_user(pool: &PgPool, name: &str) -> Result<User, sqlx::Error> {
+    pub combined_score: f64,
+}
+
+pub async fn get_or_insert_user(pool: &PgPool, session: &SessionUser) -> Result<User, sqlx::Error> {
     // Try to find the user first
-    if let Some(user) = get_user(pool, name).await? {
+    if let Some(user) = get_user(pool, session).await? {
         return Ok(user); // Return the existing user
     }
 
     // If the user doesn't exist, insert the user and return the new user
-    let row = sqlx::query!(
-        "INSERT INTO users (name) VALUES ($1) RETURNING id, name",
-        name
+    let row = sqlx::query_as!(
+        User,
+        r#"
+        INSERT INTO users (provider, provider_user_id, name) VALUES ($1, $2, $3)
+        RETURNING id, name, name AS "label!"
+        "#,
+        session.provider,
+        session.provider_user_id,
+        session.name()
     )
     .fetch_one(pool)
     .await?;
 
     // Return the newly inserted user
-    Ok(User {
-        id: row.id,
-        name: row.name,
-    })
+    Ok(row)
 }
 
-pub async fn get_user(pool: &PgPool, name: &str) -> Result<Option<User>, sqlx::Error> {
+pub async fn get_user(pool: &PgPool, session: &SessionUser) -> Result<Option<User>, sqlx::Error> {
     // Check if the user already exists
-    let row = sqlx::query_as!(User, "SELECT id, name FROM users WHERE name = $1", name)
-        .fetch_optional(pool)
-        .await?;
+    let row = sqlx::query_as!(
+        User,
+        r#"
+        SELECT id, name, COALESCE(nickname, display_name, name) AS "label!"
+        FROM users WHERE provider = $1 AND provider_user_id = $2
+        "#,
+        session.provider,
+        session.provider_user_id
+    )
+    .fetch_optional(pool)
+    .await?;
 
     Ok(row) // Return Option<User>: Some(user) if found, None if not
 }
 
-pub async fn insert_or_update_songs(
+/// Looks a voter up by the user name others know them by, e.g. in `?user=`
+pub async fn get_user_by_name(pool: &PgPool, name: &str) -> Result<Option<User>, sqlx::Error> {
+    let row = sqlx::query_as!(
+        User,
+        r#"
+        SELECT id, name, COALESCE(nickname, display_name, name) AS "label!"
+        FROM users WHERE name = $1
+        "#,
+        name
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row)
+}
+
+/// Creates the user on first login and refreshes their name, display name and avatar on every
+/// login after that. Users are matched on their provider identity. The nickname is left alone.
+pub async fn save_user_profile(
     pool: &PgPool,
-    user_id: &i32,
-    songs: &Vec<Song>,
-) -> Result<(), sqlx::Error> {
+    provider: &str,
+    provider_user_id: &str,
+    name: &str,
+    display_name: Option<&str>,
+    avatar_url: Option<&str>,
+) -> Result<User, sqlx::Error> {
+    let row = sqlx::query_as!(
+        User,
+        r#"
+        INSERT INTO users (provider, provider_user_id, name, display_name, avatar_url, profile_updated_at)
+        VALUES ($1, $2, $3, $4, $5, NOW())
+        ON CONFLICT (provider, provider_user_id) DO UPDATE
+        SET name = EXCLUDED.name,
+            display_name = EXCLUDED.display_name,
+            avatar_url = EXCLUDED.avatar_url,
+            profile_updated_at = EXCLUDED.profile_updated_at
+        RETURNING id, name, COALESCE(nickname, display_name, name) AS "label!"
+        "#,
+        provider,
+        provider_user_id,
+        name,
+        display_name,
+        avatar_url
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(row)
+}
+
+pub async fn get_user_profile(
+    pool: &PgPool,
+    session: &SessionUser,
+) -> Result<Option<UserProfile>, sqlx::Error> {
+    let row = sqlx::query_as!(
+        UserProfile,
+        r#"
+        SELECT name, display_name, avatar_url, nickname,
+            COALESCE(nickname, display_name, name) AS "label!"
+        FROM users WHERE provider = $1 AND provider_user_id = $2
+        "#,
+        session.provider,
+        session.provider_user_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row)
+}
+
+pub enum NicknameUpdate {
+    Saved(UserProfile),
+    Taken,
+}
+
+/// Sets or, given None, clears the user's nickname. Nicknames are unique ignoring case.
+pub async fn set_nickname(
+    pool: &PgPool,
+    user_id: i32,
+    nickname: Option<&str>,
+) -> Result<NicknameUpdate, sqlx::Error> {
+    let result = sqlx::query_as!(
+        UserProfile,
+        r#"
+        UPDATE users SET nickname = $2 WHERE id = $1
+        RETURNING name, display_name, avatar_url, nickname,
+            COALESCE(nickname, display_name, name) AS "label!"
+        "#,
+        user_id,
+        nickname
+    )
+    .fetch_one(pool)
+    .await;
+
+    match result {
+        Ok(profile) => Ok(NicknameUpdate::Saved(profile)),
+        Err(sqlx::Error::Database(err)) if err.is_unique_violation() => Ok(NicknameUpdate::Taken),
+        Err(err) => Err(err),
+    }
+}
+
+pub async fn create_guest_invite(
+    pool: &PgPool,
+    created_by: &i32,
+    max_uses: Option<i32>,
+    expires_at: Option<DateTime<Utc>>,
+) -> Result<GuestInvite, sqlx::Error> {
+    let row = sqlx::query_as!(
+        GuestInvite,
+        r#"
+        INSERT INTO guest_invites (code, created_by, max_uses, expires_at)
+        VALUES (UPPER(ENCODE(gen_random_bytes(5), 'hex')), $1, $2, $3)
+        RETURNING id, code, max_uses, uses, expires_at, revoked_at, created_at
+        "#,
+        created_by,
+        max_uses,
+        expires_at
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(row)
+}
+
+pub async fn get_guest_invites(pool: &PgPool) -> Result<Vec<GuestInvite>, sqlx::Error> {
+    let rows = sqlx::query_as!(
+        GuestInvite,
+        r#"
+        SELECT id, code, max_uses, uses, expires_at, revoked_at, created_at
+        FROM guest_invites
+        ORDER BY created_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows)
+}
+
+pub async fn revoke_guest_invite(pool: &PgPool, invite_id: &i32) -> Result<bool, sqlx::Error> {
+    let revoked = sqlx::query!(
+        "UPDATE guest_invites SET revoked_at = NOW() WHERE id = $1 AND revoked_at IS NULL",
+        invite_id
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    Ok(revoked > 0)
+}
+
+/// Uses up one go of the invite and creates a guest voter with a random ID, which together
+/// with their PIN is how they log back in. None if the invite is unknown, revoked, expired or
+/// used up.
+pub async fn create_guest(
+    pool: &PgPool,
+    code: &str,
+    name: &str,
+    pin: &str,
+) -> Result<Option<GuestLogin>, sqlx::Error> {
+    let mut tx: Transaction<'_, Postgres> = pool.begin().await?;
+
+    let invite_id = sqlx::query_scalar!(
+        r#"
+        UPDATE guest_invites SET uses = uses + 1
+        WHERE code = UPPER($1)
+          AND revoked_at IS NULL
+          AND (expires_at IS NULL OR expires_at > NOW())
+          AND (max_uses IS NULL OR uses < max_uses)
+        RETURNING id
+        "#,
+        code
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(invite_id) = invite_id else {
+        return Ok(None);
+    };
+
+    let guest = sqlx::query!(
+        r#"
+        WITH new_guest AS (SELECT ENCODE(gen_random_bytes(8), 'hex') AS guest_id)
+        INSERT INTO users (provider, provider_user_id, name, display_name, pin_hash, invite_id)
+        SELECT 'guest', guest_id, 'guest:user:' || guest_id, $1, crypt($2, gen_salt('bf')), $3
+        FROM new_guest
+        RETURNING provider_user_id, name, display_name, avatar_url, nickname,
+            COALESCE(nickname, display_name, name) AS "label!"
+        "#,
+        name,
+        pin,
+        invite_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(Some(GuestLogin {
+        guest_id: guest.provider_user_id,
+        profile: UserProfile {
+            name: guest.name,
+            display_name: guest.display_name,
+            avatar_url: guest.avatar_url,
+            nickname: guest.nickname,
+            label: guest.label,
+        },
+    }))
+}
+
+// Wrong PINs in a row before a guest is locked out, and how long the lockout lasts
+const MAX_PIN_ATTEMPTS: i32 = 5;
+const PIN_LOCKOUT_MINUTES: i32 = 15;
+
+/// The guest's profile if the PIN matches. Unknown guests, wrong PINs and locked out guests all
+/// get None, so callers can't tell them apart. Every wrong PIN is counted, and
+/// `MAX_PIN_ATTEMPTS` in a row lock the guest out for `PIN_LOCKOUT_MINUTES`.
+pub async fn check_guest_pin(
+    pool: &PgPool,
+    guest_id: &str,
+    pin: &str,
+) -> Result<Option<UserProfile>, sqlx::Error> {
     let mut tx: Transaction<'_, Postgres> = pool.begin().await?;
 
-    for (song) in songs {
-        // Ensure the song exists in the database, insert it if not
-        let song_id = sqlx::query!(
+    let guest = sqlx::query!(
+        r#"
+        SELECT id, pin_hash = crypt($2, pin_hash) AS "pin_matches!",
+            COALESCE(pin_locked_until > NOW(), FALSE) AS "locked!"
+        FROM users
+        WHERE provider = 'guest' AND provider_user_id = LOWER($1) AND pin_hash IS NOT NULL
+        FOR UPDATE
+        "#,
+        guest_id,
+        pin
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some(guest) = guest else {
+        // Hash anyway so an unknown ID takes as long to answer as a wrong PIN
+        sqlx::query!("SELECT crypt($1, gen_salt('bf'))", pin)
+            .fetch_one(&mut *tx)
+            .await?;
+        return Ok(None);
+    };
+    if guest.locked {
+        return Ok(None);
+    }
+
+    if !guest.pin_matches {
+        sqlx::query!(
             r#"
-            INSERT INTO songs (name, artist, uri, album_cover_url)
-            VALUES ($1, $2, $3, $4)
-            ON CONFLICT (name, artist) DO UPDATE SET
-                uri = EXCLUDED.uri,
-                album_cover_url = EXCLUDED.album_cover_url
-            RETURNING id
+            UPDATE users SET
+                failed_pin_attempts = CASE
+                    WHEN failed_pin_attempts + 1 >= $2 THEN 0 ELSE failed_pin_attempts + 1 END,
+                pin_locked_until = CASE
+                    WHEN failed_pin_attempts + 1 >= $2 THEN NOW() + MAKE_INTERVAL(mins => $3)
+                    ELSE pin_locked_until END
+            WHERE id = $1
             "#,
-            song.name,
-            song.artist,
-            song.uri,
-            song.album_cover_url
+            guest.id,
+            MAX_PIN_ATTEMPTS,
+            PIN_LOCKOUT_MINUTES
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        return Ok(None);
+    }
+
+    let profile = sqlx::query_as!(
+        UserProfile,
+        r#"
+        UPDATE users SET failed_pin_attempts = 0, pin_locked_until = NULL
+        WHERE id = $1
+        RETURNING name, display_name, avatar_url, nickname,
+            COALESCE(nickname, display_name, name) AS "label!"
+        "#,
+        guest.id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(Some(profile))
+}
+
+// Empty lists are stored as NULL so they never overwrite what we already know
+fn non_empty_json<T: Clone>(items: &[T]) -> Option<Json<Vec<T>>> {
+    (!items.is_empty()).then(|| Json(items.to_vec()))
+}
+
+// Ensure the song exists in the database, insert it if not. A song that's already there keeps
+// its catalogue data, which only the metadata backfill and song admin change.
+async fn upsert_song(tx: &mut Transaction<'_, Postgres>, song: &Song) -> Result<i32, sqlx::Error> {
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO songs (
+            name, artist, uri, album_cover_url, release_date, release_date_precision,
+            isrc, album_name, artists, duration_ms, explicit, popularity, preview_url, images,
+            normalized_name
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
+        ON CONFLICT (uri) DO NOTHING
+        RETURNING id
+        "#,
+        song.name,
+        song.artist,
+        song.uri,
+        song.album_cover_url,
+        song.release_date,
+        song.release_date_precision,
+        song.metadata.isrc,
+        song.metadata.album_name,
+        non_empty_json(&song.metadata.artists) as _,
+        song.metadata.duration_ms,
+        song.metadata.explicit,
+        song.metadata.popularity,
+        song.metadata.preview_url,
+        non_empty_json(&song.metadata.images) as _,
+        normalize_title(&song.name)
+    )
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    let Some(inserted) = inserted else {
+        let existing = sqlx::query!("SELECT id FROM songs WHERE uri = $1", song.uri)
+            .fetch_one(&mut **tx)
+            .await?;
+        return Ok(existing.id);
+    };
+
+    set_song_artists(tx, &inserted.id, song).await?;
+    link_canonical_song(tx, &inserted.id).await?;
+
+    Ok(inserted.id)
+}
+
+// Finds an artist by Spotify ID, falling back to a name-only artist we can claim the ID for
+async fn upsert_artist(
+    tx: &mut Transaction<'_, Postgres>,
+    spotify_id: Option<&str>,
+    name: &str,
+) -> Result<i32, sqlx::Error> {
+    if let Some(spotify_id) = spotify_id {
+        if let Some(row) = sqlx::query!("SELECT id FROM artists WHERE spotify_id = $1", spotify_id)
+            .fetch_optional(&mut **tx)
+            .await?
+        {
+            return Ok(row.id);
+        }
+    }
+
+    let existing = sqlx::query!(
+        r#"
+        SELECT id FROM artists
+        WHERE LOWER(name) = LOWER($1) AND ($2::text IS NULL OR spotify_id IS NULL)
+        ORDER BY spotify_id NULLS LAST, id
+        LIMIT 1
+        "#,
+        name,
+        spotify_id
+    )
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    if let Some(row) = existing {
+        if spotify_id.is_some() {
+            sqlx::query!(
+                "UPDATE artists SET spotify_id = $2 WHERE id = $1",
+                row.id,
+                spotify_id
+            )
+            .execute(&mut **tx)
+            .await?;
+        }
+        return Ok(row.id);
+    }
+
+    let artist_id = sqlx::query!(
+        "INSERT INTO artists (spotify_id, name) VALUES ($1, $2) RETURNING id",
+        spotify_id,
+        name
+    )
+    .fetch_one(&mut **tx)
+    .await?
+    .id;
+
+    Ok(artist_id)
+}
+
+// Credits every artist on the track, or just songs.artist when we don't have the full list
+async fn set_song_artists(
+    tx: &mut Transaction<'_, Postgres>,
+    song_id: &i32,
+    song: &Song,
+) -> Result<(), sqlx::Error> {
+    let credits: Vec<(Option<&str>, &str)> = if song.metadata.artists.is_empty() {
+        let credited = sqlx::query!(
+            "SELECT EXISTS (SELECT 1 FROM song_artists WHERE song_id = $1) AS \"credited!\"",
+            song_id
         )
-        .fetch_one(&mut *tx) // Use the transaction instead of the pool
+        .fetch_one(&mut **tx)
         .await?
-        .id;
+        .credited;
+        if credited {
+            return Ok(());
+        }
+        vec![(None, song.artist.as_str())]
+    } else {
+        song.metadata
+            .artists
+            .iter()
+            .map(|artist| (artist.id.as_deref(), artist.name.as_str()))
+            .collect()
+    };
+
+    sqlx::query!("DELETE FROM song_artists WHERE song_id = $1", song_id)
+        .execute(&mut **tx)
+        .await?;
 
-        // Insert or update the user's ranking for the song
+    for (index, (spotify_id, name)) in credits.into_iter().enumerate() {
+        let artist_id = upsert_artist(tx, spotify_id, name).await?;
         sqlx::query!(
             r#"
-            INSERT INTO rankings (user_id, song_id, rank)
-            VALUES ($1, $2, $3)
-            ON CONFLICT (user_id, rank) DO UPDATE SET
-                song_id = EXCLUDED.song_id
+            INSERT INTO song_artists (song_id, artist_id, role, position)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT DO NOTHING
             "#,
+            song_id,
+            artist_id,
+            if index == 0 { "primary" } else { "featured" },
+            index as i32 + 1
+        )
+        .execute(&mut **tx)
+        .await?;
+    }
+
+    Ok(())
+}
+
+// Bumps the ballot's version and keeps the full list as it now stands as a revision. Saving
+// never changes whether the ballot is submitted, a new ballot starts as a draft.
+async fn record_ballot_revision(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: &i32,
+) -> Result<i32, sqlx::Error> {
+    let ballot = sqlx::query!(
+        r#"
+        INSERT INTO ballots (user_id, version) VALUES ($1, 1)
+        ON CONFLICT (user_id) DO UPDATE SET
+            updated_at = NOW(),
+            version = ballots.version + 1
+        RETURNING version, status
+        "#,
+        user_id
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO ballot_revisions (user_id, version, poll_id, status, songs)
+        SELECT $1, $2, (SELECT id FROM polls WHERE closed_at IS NULL), $3,
+            COALESCE(
+                (SELECT JSONB_AGG(
+                    JSONB_BUILD_OBJECT('rank', r.rank, 'uri', s.uri, 'name', s.name, 'artist', s.artist)
+                    ORDER BY r.rank)
+                 FROM rankings r JOIN songs s ON r.song_id = s.id
+                 WHERE r.user_id = $1),
+                '[]'
+            )
+        "#,
+        user_id,
+        ballot.version,
+        ballot.status
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(ballot.version)
+}
+
+// A merge or its undo rewrites other people's ballots, so each of them gets a new version
+async fn record_ballot_revisions(
+    tx: &mut Transaction<'_, Postgres>,
+    user_ids: impl IntoIterator<Item = i32>,
+) -> Result<(), sqlx::Error> {
+    let user_ids: BTreeSet<i32> = user_ids.into_iter().collect();
+    for user_id in &user_ids {
+        record_ballot_revision(tx, user_id).await?;
+    }
+    Ok(())
+}
+
+pub enum BallotWrite {
+    Saved { version: i32 },
+    // The ballot changed since the version the client last saw
+    Stale { version: i32 },
+}
+
+/// Replaces the user's whole ballot with `songs` and their ranks, so ranks that aren't in the
+/// list are deleted. When `expected_version` is given the write only happens if the ballot is
+/// still at that version (0 for a user with no ballot yet).
+pub async fn replace_ballot(
+    pool: &PgPool,
+    user_id: &i32,
+    songs: &[(&Song, i32)],
+    expected_version: Option<i32>,
+) -> Result<BallotWrite, sqlx::Error> {
+    let mut tx: Transaction<'_, Postgres> = pool.begin().await?;
+
+    let current_version = sqlx::query!(
+        "SELECT version FROM ballots WHERE user_id = $1 FOR UPDATE",
+        user_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .map_or(0, |ballot| ballot.version);
+
+    if expected_version.is_some_and(|expected| expected != current_version) {
+        return Ok(BallotWrite::Stale {
+            version: current_version,
+        });
+    }
+
+    sqlx::query!("DELETE FROM rankings WHERE user_id = $1", user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    for (song, rank) in songs {
+        let song_id = upsert_song(&mut tx, song).await?;
+
+        sqlx::query!(
+            "INSERT INTO rankings (user_id, song_id, rank) VALUES ($1, $2, $3)",
             user_id,
             song_id,
-            song.rank.unwrap()
+            rank
         )
-        .execute(&mut *tx) // Use the transaction instead of the pool
+        .execute(&mut *tx)
         .await?;
     }
 
-    // Commit the transaction
+    let version = record_ballot_revision(&mut tx, user_id).await?;
+
     tx.commit().await?;
 
-    Ok(())
+    Ok(BallotWrite::Saved { version })
 }
 
 #[derive(sqlx::FromRow)]
 struct SongRow {
-    id: i32,
     name: String,
     uri: String,
     artist: String,
     album_cover_url: String,
+    release_date: Option<String>,
+    release_date_precision: Option<String>,
+    isrc: Option<String>,
+    album_name: Option<String>,
+    artists: Option<Json<Vec<TrackArtist>>>,
+    duration_ms: Option<i32>,
+    explicit: Option<bool>,
+    popularity: Option<i32>,
+    preview_url: Option<String>,
+    images: Option<Json<Vec<TrackImage>>>,
     rank: Option<i32>,
 }
 
-pub async fn get_songs_for_user_name(
-    pool: &PgPool,
-    name: &String,
-) -> Result<Vec<Song>, sqlx::Error> {
-    // Check if the user already exists
+impl From<SongRow> for Song {
+    fn from(row: SongRow) -> Self {
+        Song {
+            key: Some(format!("{}{}", row.name, row.artist)),
+            name: row.name,
+            uri: row.uri,
+            artist: row.artist,
+            album_cover_url: row.album_cover_url,
+            release_date: row.release_date,
+            release_date_precision: row.release_date_precision,
+            metadata: TrackMetadata {
+                isrc: row.isrc,
+                album_name: row.album_name,
+                artists: row.artists.map(|artists| artists.0).unwrap_or_default(),
+                duration_ms: row.duration_ms,
+                explicit: row.explicit,
+                popularity: row.popularity,
+                preview_url: row.preview_url,
+                images: row.images.map(|images| images.0).unwrap_or_default(),
+            },
+            rank: row.rank,
+        }
+    }
+}
+
+pub async fn get_songs_for_user(pool: &PgPool, user_id: &i32) -> Result<Vec<Song>, sqlx::Error> {
     let rows = sqlx::query_as!(
         SongRow,
         r#"
-            SELECT songs.*, rankings.rank FROM songs
+            SELECT songs.name, songs.uri, songs.artist, songs.album_cover_url, songs.release_date,
+                songs.release_date_precision, songs.isrc, songs.album_name,
+                songs.artists AS "artists: Json<Vec<TrackArtist>>", songs.duration_ms,
+                songs.explicit, songs.popularity, songs.preview_url,
+                songs.images AS "images: Json<Vec<TrackImage>>", rankings.rank FROM songs
             JOIN rankings ON songs.id = rankings.song_id
-            JOIN users ON rankings.user_id = users.id
-            WHERE users."name" = $1
+            WHERE rankings.user_id = $1
         "#,
-        name
+        user_id
     )
     .fetch_all(pool)
     .await?;
 
-    // Convert the results from SongRow to Song, setting `key` to None
-    let songs: Vec<Song> = rows
-        .into_iter()
-        .map(|row| Song {
-            key: Some(format!("{}{}", row.name, row.artist)), // Set key to None
-            name: row.name,
-            uri: row.uri,
-            artist: row.artist,
-            album_cover_url: row.album_cover_url,
-            rank: row.rank,
-        })
-        .collect();
+    Ok(rows.into_iter().map(Song::from).collect())
+}
 
-    Ok(songs)
+// Lowest word_similarity between a query and "title artist" that counts as a match
+const CATALOGUE_MATCH_THRESHOLD: &str = "0.4";
+
+/// Fuzzy search over songs already in the database, matched on title and artist with
+/// trigrams so partial words and typos still hit. Songs on more ballots come first, then the
+/// closest matches. This needs no Spotify call, so it answers instantly and for guests.
+pub async fn search_catalogue(pool: &PgPool, term: &str, limit: i64) -> Result<Vec<Song>, sqlx::Error> {
+    let mut tx: Transaction<'_, Postgres> = pool.begin().await?;
+
+    // The default of 0.6 misses one-letter typos in short titles. Set for this transaction
+    // only, so the `<%` below still uses the trigram index.
+    sqlx::query!(
+        "SELECT set_config('pg_trgm.word_similarity_threshold', $1, true)",
+        CATALOGUE_MATCH_THRESHOLD
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let rows = sqlx::query_as!(
+        SongRow,
+        r#"
+            SELECT s.name, s.uri, s.artist, s.album_cover_url, s.release_date,
+                s.release_date_precision, s.isrc, s.album_name,
+                s.artists AS "artists: Json<Vec<TrackArtist>>", s.duration_ms, s.explicit,
+                s.popularity, s.preview_url, s.images AS "images: Json<Vec<TrackImage>>",
+                NULL::INT AS rank
+            FROM songs s
+            LEFT JOIN (
+                SELECT song_id, COUNT(*) AS ballots FROM canonical_rankings GROUP BY song_id
+            ) votes ON votes.song_id = s.id
+            WHERE s.canonical_id IS NULL
+              AND ($1 <% (s.name || ' ' || s.artist) OR (s.name || ' ' || s.artist) ILIKE '%' || $1 || '%')
+            ORDER BY COALESCE(votes.ballots, 0) DESC,
+                word_similarity($1, s.name || ' ' || s.artist) DESC,
+                s.popularity DESC NULLS LAST
+            LIMIT $2
+        "#,
+        term,
+        limit
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(rows.into_iter().map(Song::from).collect())
 }
 
 #[derive(sqlx::FromRow, Debug)]
@@ -159,12 +753,14 @@ pub async fn get_song_rankings(pool: &PgPool) -> Result<Vec<String>, sqlx::Error
             s.uri as URI
         FROM
             songs s
-        LEFT JOIN
-            rankings r ON s.id = r.song_id
+        JOIN
+            submitted_rankings r ON s.id = r.song_id
+        WHERE
+            s.canonical_id IS NULL
         GROUP BY
             s.id, s.name
         ORDER BY
-            COUNT(r.user_id) + COALESCE(0.15 * (11-AVG(r.rank)), 0) ASC, s.name
+            COUNT(r.user_id) + 0.15 * (11-AVG(r.rank)) ASC, s.name
         "#,
     )
     .fetch_all(pool)
@@ -186,38 +782,11 @@ pub async fn get_music_taste_overview(
         r1.song_id,
         r1.rank AS user1_rank,
         r2.rank AS user2_rank,
-        ABS(r1.rank - r2.rank) AS rank_difference,
-        s.artist
-    FROM rankings r1
-    JOIN rankings r2 
+        ABS(r1.rank - r2.rank) AS rank_difference
+    FROM submitted_rankings r1
+    JOIN submitted_rankings r2 
         ON r1.song_id = r2.song_id 
         AND r1.user_id < r2.user_id
-    JOIN songs s ON r1.song_id = s.id
-),
-song_overlap AS (
-    SELECT 
-        user1_id,
-        user2_id,
-        COUNT(*) AS overlapping_songs,
-        COUNT(DISTINCT artist) AS artists_in_overlap,
-        AVG(rank_difference) AS avg_rank_difference,
-        COUNT(*) * 10.0 - AVG(rank_difference) AS song_relationship_strength
-    FROM user_pairs
-    GROUP BY user1_id, user2_id
-),
-artist_overlap AS (
-    SELECT 
-        r1.user_id AS user1_id,
-        r2.user_id AS user2_id,
-        COUNT(DISTINCT s1.artist) AS shared_artists,
-        COUNT(*) AS total_artist_overlaps,
-        AVG(ABS(r1.rank - r2.rank)) AS avg_artist_rank_diff
-    FROM rankings r1
-    JOIN rankings r2 ON r1.user_id < r2.user_id
-    JOIN songs s1 ON r1.song_id = s1.id
-    JOIN songs s2 ON r2.song_id = s2.id
-    WHERE s1.artist = s2.artist
-    GROUP BY r1.user_id, r2.user_id
 ),
 overlapping_song_details AS (
     -- Get the song details for each pair with full information
@@ -242,17 +811,19 @@ artist_detail_pairs AS (
     SELECT 
         r1.user_id AS user1_id,
         r2.user_id AS user2_id,
-        s1.artist,
+        a.name AS artist,
         s1.name AS user1_song,
         r1.rank AS user1_rank,
         s2.name AS user2_song,
         r2.rank AS user2_rank,
         ABS(r1.rank - r2.rank) AS rank_difference
-    FROM rankings r1
-    JOIN rankings r2 ON r1.user_id < r2.user_id
+    FROM submitted_rankings r1
+    JOIN submitted_rankings r2 ON r1.user_id < r2.user_id
+    JOIN song_artists sa1 ON r1.song_id = sa1.song_id
+    JOIN song_artists sa2 ON r2.song_id = sa2.song_id AND sa1.artist_id = sa2.artist_id
+    JOIN artists a ON sa1.artist_id = a.id
     JOIN songs s1 ON r1.song_id = s1.id
     JOIN songs s2 ON r2.song_id = s2.id
-    WHERE s1.artist = s2.artist
 ),
 artist_overlap_details AS (
     -- Aggregate artist details with all song combinations
@@ -271,44 +842,21 @@ artist_overlap_details AS (
         ) AS artist_details
     FROM artist_detail_pairs
     GROUP BY user1_id, user2_id
-),
-combined_metrics AS (
-    SELECT 
-        COALESCE(so.user1_id, ao.user1_id) AS user1_id,
-        COALESCE(so.user2_id, ao.user2_id) AS user2_id,
-        -- Song metrics
-        COALESCE(so.overlapping_songs, 0) AS overlapping_songs,
-        COALESCE(so.avg_rank_difference, 0) AS avg_song_rank_diff,
-        COALESCE(so.song_relationship_strength, 0) AS song_strength,
-        -- Artist metrics
-        COALESCE(ao.shared_artists, 0) AS shared_artists,
-        COALESCE(ao.total_artist_overlaps, 0) AS artist_song_overlaps,
-        COALESCE(ao.avg_artist_rank_diff, 0) AS avg_artist_rank_diff,
-        -- Combined compatibility score
-        COALESCE(so.song_relationship_strength, 0) + 
-        (COALESCE(ao.shared_artists, 0) * 3.0) - 
-        COALESCE(ao.avg_artist_rank_diff, 0) * 0.5 AS combined_compatibility_score
-    FROM song_overlap so
-    FULL OUTER JOIN artist_overlap ao
-        ON so.user1_id = ao.user1_id 
-        AND so.user2_id = ao.user2_id
-    WHERE COALESCE(so.overlapping_songs, 0) > 0 
-       OR COALESCE(ao.shared_artists, 0) > 0
 )
 SELECT 
-    u1.display_name AS user_1,
-    u2.display_name AS user_2,
+    COALESCE(u1.nickname, u1.display_name, u1.name) AS user_1,
+    COALESCE(u2.nickname, u2.display_name, u2.name) AS user_2,
     cm.overlapping_songs AS overlapping_songs,
     CAST(ROUND(cm.avg_song_rank_diff, 2) AS DOUBLE PRECISION) AS song_rank_diff,
     CAST(ROUND(cm.song_strength, 2) AS DOUBLE PRECISION) AS song_relationship_strength,
     cm.shared_artists AS overlapping_artists,
     cm.artist_song_overlaps AS total_songs_shared_artists,
     CAST(ROUND(cm.avg_artist_rank_diff, 2) AS DOUBLE PRECISION) AS artist_rank_diff,
-    CAST(ROUND(cm.combined_compatibility_score, 2) AS DOUBLE PRECISION) AS combined_score,
+    CAST(ROUND(cm.combined_score, 2) AS DOUBLE PRECISION) AS combined_score,
     -- Detailed JSON for HTML input
     COALESCE(osd.songs, '[]'::json) AS overlapping_song_details,
     COALESCE(aod.artist_details, '[]'::json) AS overlapping_artist_details
-FROM combined_metrics cm
+FROM taste_scores cm
 JOIN users u1 ON cm.user1_id = u1.id
 JOIN users u2 ON cm.user2_id = u2.id
 LEFT JOIN overlapping_song_details osd
@@ -317,8 +865,9 @@ LEFT JOIN overlapping_song_details osd
 LEFT JOIN artist_overlap_details aod
     ON cm.user1_id = aod.user1_id
     AND cm.user2_id = aod.user2_id
+WHERE cm.poll_id IS NULL
 ORDER BY 
-    cm.combined_compatibility_score DESC,
+    cm.combined_score DESC,
     cm.overlapping_songs DESC,
     cm.shared_artists DESC
 LIMIT 5;
@@ -330,128 +879,1577 @@ LIMIT 5;
     Ok(rows)
 }
 
-pub async fn get_music_taste_user(pool: &PgPool, active_user_id: &i32) -> Result<Vec<MusicTasteIndividual>, sqlx::Error> {
-
+/// How alike the user's ballot is to every other voter who shares a song or artist with them,
+/// best match first. Scores the open poll's ballots, or a closed poll's archived ones.
+pub async fn get_music_taste_user(
+    pool: &PgPool,
+    active_user_id: &i32,
+    poll_id: Option<i32>,
+) -> Result<Vec<MusicTasteIndividual>, sqlx::Error> {
     let rows = sqlx::query_as!(
         MusicTasteIndividual,
         r#"
-        WITH active_user_songs AS (
-    SELECT song_id, rank
-    FROM rankings
-    WHERE user_id = $1
-),
-other_users_songs AS (
-    SELECT user_id, song_id, rank
-    FROM rankings
-    WHERE user_id != $1
-),
-song_overlap AS (
-    SELECT 
-        ous.user_id AS other_user_id,
-        COUNT(*) AS overlapping_songs,
-        COUNT(DISTINCT s.artist) AS artists_in_overlap,
-        AVG(ABS(aus.rank - ous.rank)) AS avg_rank_difference,
-        COUNT(*) * 10.0 - AVG(ABS(aus.rank - ous.rank)) AS song_relationship_strength
-    FROM active_user_songs aus
-    JOIN other_users_songs ous ON aus.song_id = ous.song_id
-    JOIN songs s ON aus.song_id = s.id
-    GROUP BY ous.user_id
-),
-active_user_artists AS (
-    SELECT DISTINCT s.artist, r.rank, s.name as song_name
-    FROM rankings r
-    JOIN songs s ON r.song_id = s.id
-    WHERE r.user_id = $1
-),
-other_users_artists AS (
-    SELECT r.user_id, s.artist, r.rank, s.name as song_name
-    FROM rankings r
-    JOIN songs s ON r.song_id = s.id
-    WHERE r.user_id != $1
-),
-artist_overlap AS (
-    SELECT 
-        oua.user_id AS other_user_id,
-        COUNT(DISTINCT aua.artist) AS shared_artists,
-        COUNT(*) AS total_artist_overlaps,
-        AVG(ABS(aua.rank - oua.rank)) AS avg_artist_rank_diff
-    FROM active_user_artists aua
-    JOIN other_users_artists oua ON aua.artist = oua.artist
-    GROUP BY oua.user_id
-),
-overlapping_song_details AS (
-    SELECT 
-        ous.user_id AS other_user_id,
-        JSON_AGG(
-            JSON_BUILD_OBJECT(
-                'song_name', s.name,
-                'artist', s.artist,
-                'active_user_rank', aus.rank,
-                'other_user_rank', ous.rank,
-                'rank_difference', ABS(aus.rank - ous.rank)
-            ) ORDER BY ABS(aus.rank - ous.rank) ASC, aus.rank ASC
-        ) AS songs
-    FROM active_user_songs aus
-    JOIN other_users_songs ous ON aus.song_id = ous.song_id
-    JOIN songs s ON aus.song_id = s.id
-    GROUP BY ous.user_id
-),
-artist_overlap_details AS (
-    SELECT 
-        oua.user_id AS other_user_id,
-        JSON_AGG(
-            JSON_BUILD_OBJECT(
-                'artist', aua.artist,
-                'active_user_song', aua.song_name,
-                'active_user_rank', aua.rank,
-                'other_user_song', oua.song_name,
-                'other_user_rank', oua.rank,
-                'rank_difference', ABS(aua.rank - oua.rank)
-            ) ORDER BY ABS(aua.rank - oua.rank) ASC, aua.rank ASC
-        ) AS artist_details
-    FROM active_user_artists aua
-    JOIN other_users_artists oua ON aua.artist = oua.artist
-    GROUP BY oua.user_id
-),
-combined_metrics AS (
-    SELECT 
-        COALESCE(so.other_user_id, ao.other_user_id) AS other_user_id,
-        COALESCE(so.overlapping_songs, 0) AS overlapping_songs,
-        COALESCE(so.avg_rank_difference, 0) AS avg_song_rank_diff,
-        COALESCE(so.song_relationship_strength, 0) AS song_strength,
-        COALESCE(ao.shared_artists, 0) AS shared_artists,
-        COALESCE(ao.total_artist_overlaps, 0) AS artist_song_overlaps,
-        COALESCE(ao.avg_artist_rank_diff, 0) AS avg_artist_rank_diff,
-        COALESCE(so.song_relationship_strength, 0) + 
-        (COALESCE(ao.shared_artists, 0) * 3.0) - 
-        COALESCE(ao.avg_artist_rank_diff, 0) * 0.5 AS combined_compatibility_score
-    FROM song_overlap so
-    FULL OUTER JOIN artist_overlap ao ON so.other_user_id = ao.other_user_id
-    WHERE COALESCE(so.overlapping_songs, 0) > 0 
-       OR COALESCE(ao.shared_artists, 0) > 0
-)
-SELECT 
-    u.name AS other_user_name,
-    cm.overlapping_songs,
-    CAST(ROUND(cm.avg_song_rank_diff, 2) AS DOUBLE PRECISION) AS song_rank_diff,
-    CAST(ROUND(cm.song_strength, 2) AS DOUBLE PRECISION) AS song_relationship_strength,
-    cm.shared_artists AS overlapping_artists,
-    cm.artist_song_overlaps AS total_songs_shared_artists,
-    CAST(ROUND(cm.avg_artist_rank_diff, 2) AS DOUBLE PRECISION) AS artist_rank_diff,
-    CAST(ROUND(cm.combined_compatibility_score, 2) AS DOUBLE PRECISION) AS combined_score,
-    COALESCE(osd.songs, '[]'::json) AS overlapping_song_details,
-    COALESCE(aod.artist_details, '[]'::json) AS overlapping_artist_details
-FROM combined_metrics cm
-JOIN users u ON cm.other_user_id = u.id
-LEFT JOIN overlapping_song_details osd ON cm.other_user_id = osd.other_user_id
-LEFT JOIN artist_overlap_details aod ON cm.other_user_id = aod.other_user_id
-ORDER BY 
-    cm.combined_compatibility_score DESC,
-    cm.overlapping_songs DESC,
-    cm.shared_artists DESC
+        SELECT
+            u.id AS other_user_id,
+            COALESCE(u.nickname, u.display_name, u.name) AS "other_user_name!",
+            CAST(ROUND(ts.combined_score, 2) AS DOUBLE PRECISION) AS "combined_score!"
+        FROM taste_scores ts
+        JOIN users u ON u.id = CASE WHEN ts.user1_id = $1 THEN ts.user2_id ELSE ts.user1_id END
+        WHERE $1 IN (ts.user1_id, ts.user2_id) AND ts.poll_id IS NOT DISTINCT FROM $2
+        ORDER BY ts.combined_score DESC, ts.overlapping_songs DESC, ts.shared_artists DESC
+        "#,
+        active_user_id,
+        poll_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows)
+}
+
+#[derive(FromRow, Debug)]
+pub struct TastePair {
+    pub user1_id: i32,
+    pub user1_name: String,
+    pub user2_id: i32,
+    pub user2_name: String,
+    pub combined_score: f64,
+}
+
+// The taste_scores combined_score for every pair of voters, not just the overview's top 5
+pub async fn get_taste_pairs(pool: &PgPool) -> Result<Vec<TastePair>, sqlx::Error> {
+    let rows = sqlx::query_as!(
+        TastePair,
+        r#"
+        SELECT
+            u1.id AS "user1_id!",
+            COALESCE(u1.nickname, u1.display_name, u1.name) AS "user1_name!",
+            u2.id AS "user2_id!",
+            COALESCE(u2.nickname, u2.display_name, u2.name) AS "user2_name!",
+            CAST(ROUND(ts.combined_score, 2) AS DOUBLE PRECISION) AS "combined_score!"
+        FROM taste_scores ts
+        JOIN users u1 ON ts.user1_id = u1.id
+        JOIN users u2 ON ts.user2_id = u2.id
+        WHERE ts.poll_id IS NULL
+        ORDER BY u1.id, u2.id
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows)
+}
+
+#[derive(FromRow, Debug)]
+pub struct RankedVote {
+    pub user_id: i32,
+    pub user_name: String,
+    pub song_id: i32,
+    pub song_name: String,
+    pub artist: String,
+    pub artists: Vec<String>,
+    pub uri: String,
+    pub album_cover_url: String,
+    pub rank: i32,
+}
+
+// Every canonical song on the user's ballot, whether it's been submitted or is still a draft
+pub async fn get_ranked_song_ids(pool: &PgPool, user_id: &i32) -> Result<HashSet<i32>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"SELECT song_id AS "song_id!" FROM canonical_rankings WHERE user_id = $1"#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|row| row.song_id).collect())
+}
+
+pub async fn get_all_rankings(pool: &PgPool) -> Result<Vec<RankedVote>, sqlx::Error> {
+    let rows = sqlx::query_as!(
+        RankedVote,
+        r#"
+        SELECT
+            u.id AS user_id,
+            COALESCE(u.nickname, u.display_name, u.name) AS "user_name!",
+            s.id AS song_id,
+            s.name AS song_name,
+            s.artist,
+            COALESCE(
+                (SELECT ARRAY_AGG(a.name ORDER BY sa.position)
+                 FROM song_artists sa
+                 JOIN artists a ON sa.artist_id = a.id
+                 WHERE sa.song_id = s.id),
+                ARRAY[s.artist]
+            ) AS "artists!",
+            s.uri,
+            s.album_cover_url,
+            r.rank AS "rank!"
+        FROM submitted_rankings r
+        JOIN users u ON r.user_id = u.id
+        JOIN songs s ON r.song_id = s.id
+        ORDER BY u.id, r.rank
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows)
+}
+
+pub async fn get_voters(pool: &PgPool) -> Result<Vec<User>, sqlx::Error> {
+    let rows = sqlx::query_as!(
+        User,
+        r#"
+        SELECT u.id, u.name, COALESCE(u.nickname, u.display_name, u.name) AS "label!"
+        FROM users u
+        JOIN ballots b ON b.user_id = u.id AND b.status = 'submitted'
+        WHERE EXISTS (SELECT 1 FROM rankings r WHERE r.user_id = u.id)
+        ORDER BY u.id
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows)
+}
+
+pub async fn get_open_poll(pool: &PgPool) -> Result<Option<Poll>, sqlx::Error> {
+    let row = sqlx::query_as!(
+        Poll,
+        "SELECT id, name, created_at, closed_at, eligible_from, eligible_to FROM polls WHERE closed_at IS NULL"
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row)
+}
+
+pub async fn get_latest_closed_poll(pool: &PgPool) -> Result<Option<Poll>, sqlx::Error> {
+    let row = sqlx::query_as!(
+        Poll,
+        r#"
+        SELECT id, name, created_at, closed_at, eligible_from, eligible_to FROM polls
+        WHERE closed_at IS NOT NULL
+        ORDER BY closed_at DESC
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row)
+}
+
+/// The poll that closed before `poll`, for comparing one poll with the last
+pub async fn get_previous_closed_poll(pool: &PgPool, poll: &Poll) -> Result<Option<Poll>, sqlx::Error> {
+    let row = sqlx::query_as!(
+        Poll,
+        r#"
+        SELECT id, name, created_at, closed_at, eligible_from, eligible_to FROM polls
+        WHERE closed_at < $1
+        ORDER BY closed_at DESC
+        LIMIT 1
+        "#,
+        poll.closed_at
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row)
+}
+
+pub async fn create_poll(pool: &PgPool, name: &str) -> Result<Poll, sqlx::Error> {
+    let row = sqlx::query_as!(
+        Poll,
+        "INSERT INTO polls (name) VALUES ($1) RETURNING id, name, created_at, closed_at, eligible_from, eligible_to",
+        name
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(row)
+}
+
+// Archives every ballot, drafts included, and the final countdown into the poll, then clears the
+// ballots so the next poll starts empty. Drafts are marked as not submitted in the archive.
+// `countdown_uris` is in get_song_rankings order (last entry is #1).
+pub async fn close_poll(
+    pool: &PgPool,
+    poll_id: &i32,
+    countdown_uris: &[String],
+) -> Result<(), sqlx::Error> {
+    let mut tx: Transaction<'_, Postgres> = pool.begin().await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO poll_rankings (poll_id, user_id, song_id, rank, submitted)
+        SELECT $1, r.user_id, r.song_id, r.rank, COALESCE(b.status = 'submitted', FALSE)
+        FROM rankings r
+        LEFT JOIN ballots b ON b.user_id = r.user_id
+        WHERE r.user_id IS NOT NULL AND r.song_id IS NOT NULL AND r.rank IS NOT NULL
+        "#,
+        poll_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let positions: Vec<i32> = (1..=countdown_uris.len() as i32).rev().collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO poll_results (poll_id, song_id, position)
+        SELECT $1, s.id, countdown.position
+        FROM UNNEST($2::text[], $3::int[]) AS countdown(uri, position)
+        JOIN songs s ON s.uri = countdown.uri
+        "#,
+        poll_id,
+        countdown_uris,
+        &positions
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!("DELETE FROM rankings").execute(&mut *tx).await?;
+    // Ballots keep their version so revision history carries on into the next poll
+    sqlx::query!("UPDATE ballots SET status = 'draft', submitted_at = NULL")
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query!(
+        "UPDATE polls SET closed_at = NOW() WHERE id = $1",
+        poll_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+#[derive(FromRow, Debug)]
+pub struct PollPick {
+    // The canonical song, so variants of one song match each other
+    pub song_id: i32,
+    pub name: String,
+    pub artist: String,
+    pub rank: i32,
+    pub position: Option<i32>,
+}
+
+pub async fn get_poll_picks_for_user(
+    pool: &PgPool,
+    poll_id: &i32,
+    user_id: &i32,
+) -> Result<Vec<PollPick>, sqlx::Error> {
+    let rows = sqlx::query_as!(
+        PollPick,
+        r#"
+        SELECT COALESCE(s.canonical_id, s.id) AS "song_id!", s.name, s.artist, pr.rank,
+            res.position AS "position?"
+        FROM poll_rankings pr
+        JOIN songs s ON pr.song_id = s.id
+        LEFT JOIN poll_results res
+            ON res.poll_id = pr.poll_id AND res.song_id = COALESCE(s.canonical_id, s.id)
+        WHERE pr.poll_id = $1 AND pr.user_id = $2 AND pr.submitted
+        ORDER BY pr.rank
+        "#,
+        poll_id,
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows)
+}
+
+/// How many submitted ballots in a closed poll picked each canonical song
+pub async fn get_poll_vote_counts(
+    pool: &PgPool,
+    poll_id: &i32,
+) -> Result<HashMap<i32, i64>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT song_id AS "song_id!", COUNT(*) AS "votes!"
+        FROM poll_submitted_rankings
+        WHERE poll_id = $1
+        GROUP BY song_id
+        "#,
+        poll_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|row| (row.song_id, row.votes)).collect())
+}
+
+pub async fn get_poll(pool: &PgPool, poll_id: &i32) -> Result<Option<Poll>, sqlx::Error> {
+    let row = sqlx::query_as!(
+        Poll,
+        "SELECT id, name, created_at, closed_at, eligible_from, eligible_to FROM polls WHERE id = $1",
+        poll_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row)
+}
+
+pub async fn get_latest_poll_with_official_results(
+    pool: &PgPool,
+) -> Result<Option<Poll>, sqlx::Error> {
+    let row = sqlx::query_as!(
+        Poll,
+        r#"
+        SELECT p.id, p.name, p.created_at, p.closed_at, p.eligible_from, p.eligible_to FROM polls p
+        WHERE EXISTS (SELECT 1 FROM official_results o WHERE o.poll_id = p.id)
+        ORDER BY p.created_at DESC
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row)
+}
+
+// Replaces the user's whole prediction ballot, `rank` on each song is the predicted position
+pub async fn replace_predictions(
+    pool: &PgPool,
+    poll_id: &i32,
+    user_id: &i32,
+    songs: &Vec<Song>,
+) -> Result<(), sqlx::Error> {
+    let mut tx: Transaction<'_, Postgres> = pool.begin().await?;
+
+    sqlx::query!(
+        "DELETE FROM predictions WHERE poll_id = $1 AND user_id = $2",
+        poll_id,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    for song in songs {
+        let song_id = upsert_song(&mut tx, song).await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO predictions (poll_id, user_id, song_id, position)
+            VALUES ($1, $2, $3, $4)
+            "#,
+            poll_id,
+            user_id,
+            song_id,
+            song.rank
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+pub async fn get_predictions_for_user(
+    pool: &PgPool,
+    poll_id: &i32,
+    user_id: &i32,
+) -> Result<Vec<Song>, sqlx::Error> {
+    let rows = sqlx::query_as!(
+        SongRow,
+        r#"
+            SELECT s.name, s.uri, s.artist, s.album_cover_url, s.release_date,
+                s.release_date_precision, s.isrc, s.album_name,
+                s.artists AS "artists: Json<Vec<TrackArtist>>", s.duration_ms, s.explicit,
+                s.popularity, s.preview_url, s.images AS "images: Json<Vec<TrackImage>>",
+                p.position AS rank FROM songs s
+            JOIN predictions p ON s.id = p.song_id
+            WHERE p.poll_id = $1 AND p.user_id = $2
+            ORDER BY p.position
+        "#,
+        poll_id,
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(Song::from).collect())
+}
+
+// Matches on URI when given, otherwise on a case-insensitive name and artist
+pub async fn find_song_id(
+    pool: &PgPool,
+    uri: Option<&str>,
+    name: Option<&str>,
+    artist: Option<&str>,
+) -> Result<Option<i32>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT COALESCE(canonical_id, id) AS "id!" FROM songs
+        WHERE ($1::text IS NOT NULL AND uri = $1)
+           OR ($1::text IS NULL AND LOWER(name) = LOWER($2) AND LOWER(artist) = LOWER($3))
+        ORDER BY canonical_id NULLS FIRST, id
+        LIMIT 1
+        "#,
+        uri,
+        name,
+        artist
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| row.id))
+}
+
+pub async fn replace_official_results(
+    pool: &PgPool,
+    poll_id: &i32,
+    results: &[(i32, i32)],
+) -> Result<(), sqlx::Error> {
+    let mut tx: Transaction<'_, Postgres> = pool.begin().await?;
+
+    sqlx::query!("DELETE FROM official_results WHERE poll_id = $1", poll_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let song_ids: Vec<i32> = results.iter().map(|(song_id, _)| *song_id).collect();
+    let positions: Vec<i32> = results.iter().map(|(_, position)| *position).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO official_results (poll_id, song_id, position)
+        SELECT $1, song_id, position
+        FROM UNNEST($2::int[], $3::int[]) AS official(song_id, position)
+        ON CONFLICT (poll_id, song_id) DO UPDATE SET
+            position = LEAST(official_results.position, EXCLUDED.position)
+        "#,
+        poll_id,
+        &song_ids,
+        &positions
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+#[derive(FromRow, Debug)]
+pub struct PlacedSong {
+    pub user_id: i32,
+    pub user_name: String,
+    pub song_id: i32,
+    pub position: i32,
+}
+
+pub async fn get_predictions(pool: &PgPool, poll_id: &i32) -> Result<Vec<PlacedSong>, sqlx::Error> {
+    let rows = sqlx::query_as!(
+        PlacedSong,
+        r#"
+        SELECT DISTINCT ON (p.user_id, COALESCE(s.canonical_id, s.id))
+            p.user_id,
+            COALESCE(u.nickname, u.display_name, u.name) AS "user_name!",
+            COALESCE(s.canonical_id, s.id) AS "song_id!",
+            p.position
+        FROM predictions p
+        JOIN users u ON p.user_id = u.id
+        JOIN songs s ON p.song_id = s.id
+        WHERE p.poll_id = $1
+        ORDER BY p.user_id, COALESCE(s.canonical_id, s.id), p.position
+        "#,
+        poll_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows)
+}
+
+#[derive(FromRow, Debug)]
+pub struct OfficialResult {
+    pub song_id: i32,
+    pub position: i32,
+}
+
+pub async fn get_official_results(
+    pool: &PgPool,
+    poll_id: &i32,
+) -> Result<Vec<OfficialResult>, sqlx::Error> {
+    let rows = sqlx::query_as!(
+        OfficialResult,
+        r#"
+        SELECT DISTINCT ON (COALESCE(s.canonical_id, s.id))
+            COALESCE(s.canonical_id, s.id) AS "song_id!",
+            o.position
+        FROM official_results o
+        JOIN songs s ON o.song_id = s.id
+        WHERE o.poll_id = $1
+        ORDER BY COALESCE(s.canonical_id, s.id), o.position
+        "#,
+        poll_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows)
+}
+
+#[derive(FromRow, Debug)]
+pub struct CountdownSong {
+    pub uri: String,
+    pub name: String,
+    pub artist: String,
+    pub position: i32,
+}
+
+// Looks up the songs for a get_song_rankings list, which runs from the bottom up (last is #1)
+pub async fn get_countdown_songs(
+    pool: &PgPool,
+    countdown_uris: &[String],
+) -> Result<Vec<CountdownSong>, sqlx::Error> {
+    let rows = sqlx::query_as!(
+        CountdownSong,
+        r#"
+        SELECT
+            s.uri,
+            s.name,
+            s.artist,
+            CAST(CARDINALITY($1::text[]) + 1 - countdown.idx AS INT) AS "position!"
+        FROM UNNEST($1::text[]) WITH ORDINALITY AS countdown(uri, idx)
+        JOIN songs s ON s.uri = countdown.uri
+        ORDER BY countdown.idx DESC
         "#,
-active_user_id
-    ).fetch_all(pool).await?;
+        countdown_uris
+    )
+    .fetch_all(pool)
+    .await?;
 
     Ok(rows)
 }
+
+pub async fn get_poll_countdown(
+    pool: &PgPool,
+    poll_id: &i32,
+) -> Result<Vec<CountdownSong>, sqlx::Error> {
+    let rows = sqlx::query_as!(
+        CountdownSong,
+        r#"
+        SELECT s.uri, s.name, s.artist, res.position
+        FROM poll_results res
+        JOIN songs s ON res.song_id = s.id
+        WHERE res.poll_id = $1
+        ORDER BY res.position
+        "#,
+        poll_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows)
+}
+
+pub async fn get_official_countdown(
+    pool: &PgPool,
+    poll_id: &i32,
+) -> Result<Vec<CountdownSong>, sqlx::Error> {
+    let rows = sqlx::query_as!(
+        CountdownSong,
+        r#"
+        SELECT s.uri, s.name, s.artist, o.position
+        FROM official_results o
+        JOIN songs s ON o.song_id = s.id
+        WHERE o.poll_id = $1
+        ORDER BY o.position
+        "#,
+        poll_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows)
+}
+
+pub async fn set_poll_eligibility(
+    pool: &PgPool,
+    poll_id: &i32,
+    eligible_from: Option<NaiveDate>,
+    eligible_to: Option<NaiveDate>,
+) -> Result<Option<Poll>, sqlx::Error> {
+    let row = sqlx::query_as!(
+        Poll,
+        r#"
+        UPDATE polls SET eligible_from = $2, eligible_to = $3
+        WHERE id = $1
+        RETURNING id, name, created_at, closed_at, eligible_from, eligible_to
+        "#,
+        poll_id,
+        eligible_from,
+        eligible_to
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row)
+}
+
+pub async fn get_ballot_rules(
+    pool: &PgPool,
+    poll_id: &i32,
+) -> Result<Option<BallotRules>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT ballot_rules AS "ballot_rules: Json<BallotRules>" FROM polls WHERE id = $1"#,
+        poll_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| row.ballot_rules.0))
+}
+
+pub async fn set_ballot_rules(
+    pool: &PgPool,
+    poll_id: &i32,
+    rules: &BallotRules,
+) -> Result<bool, sqlx::Error> {
+    let updated = sqlx::query!(
+        "UPDATE polls SET ballot_rules = $2 WHERE id = $1",
+        poll_id,
+        Json(rules) as _
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    Ok(updated > 0)
+}
+
+pub async fn add_eligibility_override(
+    pool: &PgPool,
+    poll_id: &i32,
+    uri: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO eligibility_overrides (poll_id, uri) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        poll_id,
+        uri
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn remove_eligibility_override(
+    pool: &PgPool,
+    poll_id: &i32,
+    uri: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM eligibility_overrides WHERE poll_id = $1 AND uri = $2",
+        poll_id,
+        uri
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn get_eligibility_overrides(
+    pool: &PgPool,
+    poll_id: &i32,
+) -> Result<Vec<String>, sqlx::Error> {
+    let rows = sqlx::query!(
+        "SELECT uri FROM eligibility_overrides WHERE poll_id = $1 ORDER BY uri",
+        poll_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|row| row.uri).collect())
+}
+
+pub struct ReleaseDate {
+    pub uri: String,
+    pub release_date: String,
+    pub release_date_precision: Option<String>,
+}
+
+// The release dates already on record for these songs, songs without one are left out
+pub async fn get_release_dates(
+    pool: &PgPool,
+    uris: &[String],
+) -> Result<Vec<ReleaseDate>, sqlx::Error> {
+    let rows = sqlx::query_as!(
+        ReleaseDate,
+        r#"
+        SELECT uri, release_date AS "release_date!", release_date_precision FROM songs
+        WHERE uri = ANY($1) AND release_date IS NOT NULL
+        "#,
+        uris
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows)
+}
+
+// Songs the backfill job hasn't looked up yet
+pub async fn get_songs_missing_metadata(pool: &PgPool) -> Result<Vec<String>, sqlx::Error> {
+    let rows = sqlx::query!(
+        "SELECT uri FROM songs WHERE metadata_updated_at IS NULL ORDER BY id"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|row| row.uri).collect())
+}
+
+pub async fn update_song_metadata(pool: &PgPool, song: &Song) -> Result<(), sqlx::Error> {
+    let mut tx: Transaction<'_, Postgres> = pool.begin().await?;
+
+    let updated = sqlx::query!(
+        r#"
+        UPDATE songs SET
+            album_cover_url = CASE WHEN $2 = '' THEN album_cover_url ELSE $2 END,
+            release_date = COALESCE($3, release_date),
+            release_date_precision = COALESCE($4, release_date_precision),
+            isrc = COALESCE($5, isrc),
+            album_name = COALESCE($6, album_name),
+            artists = COALESCE($7, artists),
+            duration_ms = COALESCE($8, duration_ms),
+            explicit = COALESCE($9, explicit),
+            popularity = COALESCE($10, popularity),
+            preview_url = COALESCE($11, preview_url),
+            images = COALESCE($12, images),
+            metadata_updated_at = NOW()
+        WHERE uri = $1
+        RETURNING id
+        "#,
+        song.uri,
+        song.album_cover_url,
+        song.release_date,
+        song.release_date_precision,
+        song.metadata.isrc,
+        song.metadata.album_name,
+        non_empty_json(&song.metadata.artists) as _,
+        song.metadata.duration_ms,
+        song.metadata.explicit,
+        song.metadata.popularity,
+        song.metadata.preview_url,
+        non_empty_json(&song.metadata.images) as _
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if let Some(row) = updated {
+        set_song_artists(&mut tx, &row.id, song).await?;
+        link_canonical_song(&mut tx, &row.id).await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+// Points a song, and every version already folded into it, at a new canonical song
+async fn set_canonical(
+    tx: &mut Transaction<'_, Postgres>,
+    song_id: &i32,
+    canonical_id: &i32,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE songs SET canonical_id = $2 WHERE id = $1 OR canonical_id = $1",
+        song_id,
+        canonical_id
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+// Folds a song into an existing song with the same ISRC, or records a merge suggestion for a
+// song by the same primary artist whose title normalises the same. The oldest song stays
+// canonical, and pairs an admin rejected or split apart are left alone. Returns "isrc" or
+// "title" when either happened.
+async fn link_canonical_song(
+    tx: &mut Transaction<'_, Postgres>,
+    song_id: &i32,
+) -> Result<Option<&'static str>, sqlx::Error> {
+    let isrc_match = sqlx::query!(
+        r#"
+        SELECT COALESCE(me.canonical_id, me.id) AS "own_root!",
+            MIN(COALESCE(other.canonical_id, other.id)) AS "other_root!"
+        FROM songs me
+        JOIN songs other ON other.isrc = me.isrc
+            AND COALESCE(other.canonical_id, other.id) != COALESCE(me.canonical_id, me.id)
+            AND NOT EXISTS (
+                SELECT 1 FROM song_merge_suggestions m
+                WHERE m.status = 'rejected'
+                  AND ((m.song_id = me.id AND m.canonical_id IN (other.id, COALESCE(other.canonical_id, other.id)))
+                    OR (m.canonical_id = me.id AND m.song_id IN (other.id, COALESCE(other.canonical_id, other.id))))
+            )
+        WHERE me.id = $1
+        GROUP BY me.id
+        "#,
+        song_id
+    )
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    if let Some(row) = isrc_match {
+        let (variant, canonical) = if row.own_root < row.other_root {
+            (row.other_root, row.own_root)
+        } else {
+            (row.own_root, row.other_root)
+        };
+        set_canonical(tx, &variant, &canonical).await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO song_merge_suggestions (song_id, canonical_id, reason, status, reviewed_at)
+            VALUES ($1, $2, 'isrc', 'confirmed', NOW())
+            ON CONFLICT (song_id, canonical_id) DO NOTHING
+            "#,
+            variant,
+            canonical
+        )
+        .execute(&mut **tx)
+        .await?;
+        return Ok(Some("isrc"));
+    }
+
+    let title_match = sqlx::query!(
+        r#"
+        SELECT COALESCE(me.canonical_id, me.id) AS "own_root!",
+            MIN(COALESCE(other.canonical_id, other.id)) AS "other_root!"
+        FROM songs me
+        JOIN song_artists my_artist ON my_artist.song_id = me.id AND my_artist.position = 1
+        JOIN song_artists their_artist ON their_artist.artist_id = my_artist.artist_id
+            AND their_artist.position = 1
+        JOIN songs other ON other.id = their_artist.song_id
+            AND other.normalized_name = me.normalized_name
+            AND COALESCE(other.canonical_id, other.id) != COALESCE(me.canonical_id, me.id)
+            AND NOT EXISTS (
+                SELECT 1 FROM song_merge_suggestions m
+                WHERE m.status = 'rejected'
+                  AND ((m.song_id = me.id AND m.canonical_id IN (other.id, COALESCE(other.canonical_id, other.id)))
+                    OR (m.canonical_id = me.id AND m.song_id IN (other.id, COALESCE(other.canonical_id, other.id))))
+            )
+        WHERE me.id = $1
+        GROUP BY me.id
+        "#,
+        song_id
+    )
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    let Some(row) = title_match else {
+        return Ok(None);
+    };
+    let (variant, canonical) = if row.own_root < row.other_root {
+        (row.other_root, row.own_root)
+    } else {
+        (row.own_root, row.other_root)
+    };
+    let suggested = sqlx::query!(
+        r#"
+        INSERT INTO song_merge_suggestions (song_id, canonical_id, reason)
+        VALUES ($1, $2, 'title')
+        ON CONFLICT (song_id, canonical_id) DO NOTHING
+        "#,
+        variant,
+        canonical
+    )
+    .execute(&mut **tx)
+    .await?
+    .rows_affected();
+
+    Ok((suggested > 0).then_some("title"))
+}
+
+/// Recomputes normalised titles for every song and links or suggests merges across the whole
+/// catalogue, for songs saved before canonicalisation existed.
+pub async fn scan_canonical_songs(pool: &PgPool) -> Result<CanonicalScan, sqlx::Error> {
+    let mut tx: Transaction<'_, Postgres> = pool.begin().await?;
+
+    let songs = sqlx::query!("SELECT id, name FROM songs ORDER BY id")
+        .fetch_all(&mut *tx)
+        .await?;
+
+    for song in &songs {
+        sqlx::query!(
+            "UPDATE songs SET normalized_name = $2 WHERE id = $1",
+            song.id,
+            normalize_title(&song.name)
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    let mut scan = CanonicalScan::default();
+    for song in &songs {
+        match link_canonical_song(&mut tx, &song.id).await? {
+            Some("isrc") => scan.linked += 1,
+            Some(_) => scan.suggested += 1,
+            None => {}
+        }
+    }
+
+    tx.commit().await?;
+
+    Ok(scan)
+}
+
+pub async fn get_merge_suggestions(
+    pool: &PgPool,
+    status: &str,
+) -> Result<Vec<MergeSuggestion>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            m.id, m.reason, m.status, m.reviewed_by,
+            s.id AS song_id, s.name AS song_name, s.artist AS song_artist, s.uri AS song_uri,
+            s.album_name AS song_album, s.isrc AS song_isrc,
+            (SELECT COUNT(*) FROM rankings r WHERE r.song_id = s.id) AS "song_votes!",
+            c.id AS canonical_song_id, c.name AS canonical_name, c.artist AS canonical_artist,
+            c.uri AS canonical_uri, c.album_name AS canonical_album, c.isrc AS canonical_isrc,
+            (SELECT COUNT(*) FROM rankings r WHERE r.song_id = c.id) AS "canonical_votes!"
+        FROM song_merge_suggestions m
+        JOIN songs s ON m.song_id = s.id
+        JOIN songs c ON m.canonical_id = c.id
+        WHERE m.status = $1
+        ORDER BY m.created_at, m.id
+        "#,
+        status
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| MergeSuggestion {
+            id: row.id,
+            reason: row.reason,
+            status: row.status,
+            reviewed_by: row.reviewed_by,
+            song: MergeCandidate {
+                id: row.song_id,
+                name: row.song_name,
+                artist: row.song_artist,
+                uri: row.song_uri,
+                album_name: row.song_album,
+                isrc: row.song_isrc,
+                votes: row.song_votes,
+            },
+            canonical: MergeCandidate {
+                id: row.canonical_song_id,
+                name: row.canonical_name,
+                artist: row.canonical_artist,
+                uri: row.canonical_uri,
+                album_name: row.canonical_album,
+                isrc: row.canonical_isrc,
+                votes: row.canonical_votes,
+            },
+        })
+        .collect())
+}
+
+/// Confirms or rejects a pending suggestion, folding the song into the canonical one when
+/// confirmed. Returns false if there is no pending suggestion with that id.
+pub async fn review_merge_suggestion(
+    pool: &PgPool,
+    suggestion_id: &i32,
+    confirm: bool,
+    reviewed_by: &str,
+) -> Result<bool, sqlx::Error> {
+    let mut tx: Transaction<'_, Postgres> = pool.begin().await?;
+
+    let suggestion = sqlx::query!(
+        r#"
+        UPDATE song_merge_suggestions
+        SET status = $2, reviewed_at = NOW(), reviewed_by = $3
+        WHERE id = $1 AND status = 'pending'
+        RETURNING song_id, canonical_id
+        "#,
+        suggestion_id,
+        if confirm { "confirmed" } else { "rejected" },
+        reviewed_by
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some(suggestion) = suggestion else {
+        return Ok(false);
+    };
+
+    if confirm {
+        // Either side may have been folded into something else since the suggestion was made
+        let roots = sqlx::query!(
+            r#"
+            SELECT
+                (SELECT COALESCE(canonical_id, id) FROM songs WHERE id = $1) AS "song_root!",
+                (SELECT COALESCE(canonical_id, id) FROM songs WHERE id = $2) AS "canonical_root!"
+            "#,
+            suggestion.song_id,
+            suggestion.canonical_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        if roots.song_root != roots.canonical_root {
+            set_canonical(&mut tx, &roots.song_root, &roots.canonical_root).await?;
+        }
+    }
+
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+/// Moves every vote and prediction for `from_song_id` onto `into_song_id` and folds the song into
+/// it. A voter who ranked both keeps their better rank and the other vote is dropped. What was
+/// moved and dropped is recorded so the merge can be undone, and every ballot it touched gets a
+/// new version. Returns None if either song doesn't exist.
+pub async fn merge_songs(
+    pool: &PgPool,
+    from_song_id: &i32,
+    into_song_id: &i32,
+    merged_by: &str,
+) -> Result<Option<SongMerge>, sqlx::Error> {
+    let mut tx: Transaction<'_, Postgres> = pool.begin().await?;
+
+    let songs = sqlx::query!(
+        r#"
+        SELECT from_song.canonical_id AS from_canonical_id,
+            COALESCE(from_song.canonical_id, from_song.id) AS "from_root!",
+            COALESCE(into_song.canonical_id, into_song.id) AS "into_root!"
+        FROM songs from_song, songs into_song
+        WHERE from_song.id = $1 AND into_song.id = $2
+        "#,
+        from_song_id,
+        into_song_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some(songs) = songs else {
+        return Ok(None);
+    };
+
+    let dropped = sqlx::query_as!(
+        MergedRanking,
+        r#"
+        DELETE FROM rankings r
+        USING rankings other
+        WHERE r.user_id = other.user_id
+          AND r.song_id IN ($1, $2)
+          AND other.song_id IN ($1, $2)
+          AND r.song_id != other.song_id
+          AND r.rank > other.rank
+        RETURNING r.user_id AS "user_id!", r.song_id AS "song_id!", r.rank AS "rank!"
+        "#,
+        from_song_id,
+        into_song_id
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let moved = sqlx::query_as!(
+        MergedRanking,
+        r#"
+        UPDATE rankings SET song_id = $2
+        WHERE song_id = $1
+        RETURNING user_id AS "user_id!", $1::int AS "song_id!", rank AS "rank!"
+        "#,
+        from_song_id,
+        into_song_id
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    // Predictions follow the same rule: one per poll, keeping the better position
+    let dropped_predictions = sqlx::query_as!(
+        MergedPrediction,
+        r#"
+        DELETE FROM predictions p
+        USING predictions other
+        WHERE p.poll_id = other.poll_id
+          AND p.user_id = other.user_id
+          AND p.song_id IN ($1, $2)
+          AND other.song_id IN ($1, $2)
+          AND p.song_id != other.song_id
+          AND p.position > other.position
+        RETURNING p.poll_id AS "poll_id!", p.user_id AS "user_id!", p.song_id AS "song_id!",
+            p.position AS "position!"
+        "#,
+        from_song_id,
+        into_song_id
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let moved_predictions = sqlx::query_as!(
+        MergedPrediction,
+        r#"
+        UPDATE predictions SET song_id = $2
+        WHERE song_id = $1
+        RETURNING poll_id AS "poll_id!", user_id AS "user_id!", $1::int AS "song_id!",
+            position AS "position!"
+        "#,
+        from_song_id,
+        into_song_id
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    // Fold the song into the target unless they already share a canonical song
+    let mut relinked: Vec<i32> = Vec::new();
+    if songs.from_root != songs.into_root {
+        relinked = sqlx::query!(
+            r#"
+            UPDATE songs SET canonical_id = $2
+            WHERE canonical_id = $1
+            RETURNING id
+            "#,
+            from_song_id,
+            songs.into_root
+        )
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(|row| row.id)
+        .collect();
+
+        sqlx::query!(
+            "UPDATE songs SET canonical_id = $2 WHERE id = $1",
+            from_song_id,
+            songs.into_root
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    record_ballot_revisions(
+        &mut tx,
+        moved.iter().chain(&dropped).map(|ranking| ranking.user_id),
+    )
+    .await?;
+
+    let merge = sqlx::query!(
+        r#"
+        INSERT INTO song_merges (
+            from_song_id, into_song_id, previous_canonical_id, relinked_song_ids,
+            moved_rankings, dropped_rankings, moved_predictions, dropped_predictions, merged_by
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        RETURNING id, merged_at
+        "#,
+        from_song_id,
+        into_song_id,
+        songs.from_canonical_id,
+        &relinked,
+        Json(&moved) as _,
+        Json(&dropped) as _,
+        Json(&moved_predictions) as _,
+        Json(&dropped_predictions) as _,
+        merged_by
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(SongMerge {
+        id: merge.id,
+        from_song_id: *from_song_id,
+        into_song_id: *into_song_id,
+        moved_rankings: moved,
+        dropped_rankings: dropped,
+        merged_by: merged_by.to_string(),
+        merged_at: merge.merged_at,
+        undone_by: None,
+        undone_at: None,
+    }))
+}
+
+struct SongMergeRow {
+    id: i32,
+    from_song_id: i32,
+    into_song_id: i32,
+    moved_rankings: Json<Vec<MergedRanking>>,
+    dropped_rankings: Json<Vec<MergedRanking>>,
+    merged_by: String,
+    merged_at: DateTime<Utc>,
+    undone_by: Option<String>,
+    undone_at: Option<DateTime<Utc>>,
+}
+
+impl From<SongMergeRow> for SongMerge {
+    fn from(row: SongMergeRow) -> Self {
+        SongMerge {
+            id: row.id,
+            from_song_id: row.from_song_id,
+            into_song_id: row.into_song_id,
+            moved_rankings: row.moved_rankings.0,
+            dropped_rankings: row.dropped_rankings.0,
+            merged_by: row.merged_by,
+            merged_at: row.merged_at,
+            undone_by: row.undone_by,
+            undone_at: row.undone_at,
+        }
+    }
+}
+
+pub async fn get_song_merges(pool: &PgPool) -> Result<Vec<SongMerge>, sqlx::Error> {
+    let rows = sqlx::query_as!(
+        SongMergeRow,
+        r#"
+        SELECT id, from_song_id, into_song_id,
+            moved_rankings AS "moved_rankings: Json<Vec<MergedRanking>>",
+            dropped_rankings AS "dropped_rankings: Json<Vec<MergedRanking>>",
+            merged_by, merged_at, undone_by, undone_at
+        FROM song_merges
+        ORDER BY merged_at DESC, id DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(SongMerge::from).collect())
+}
+
+/// Puts moved votes back on the original song and restores dropped ones, giving each ballot it
+/// touched a new version. Votes the voter has since changed are left as they are and counted as
+/// skipped. Returns None if the merge doesn't
+/// exist or was already undone.
+pub async fn undo_song_merge(
+    pool: &PgPool,
+    merge_id: &i32,
+    undone_by: &str,
+) -> Result<Option<SongMergeUndo>, sqlx::Error> {
+    let mut tx: Transaction<'_, Postgres> = pool.begin().await?;
+
+    let merge = sqlx::query!(
+        r#"
+        UPDATE song_merges SET undone_at = NOW(), undone_by = $2
+        WHERE id = $1 AND undone_at IS NULL
+        RETURNING from_song_id, into_song_id, previous_canonical_id, relinked_song_ids,
+            moved_rankings AS "moved_rankings: Json<Vec<MergedRanking>>",
+            dropped_rankings AS "dropped_rankings: Json<Vec<MergedRanking>>",
+            moved_predictions AS "moved_predictions: Json<Vec<MergedPrediction>>",
+            dropped_predictions AS "dropped_predictions: Json<Vec<MergedPrediction>>"
+        "#,
+        merge_id,
+        undone_by
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some(merge) = merge else {
+        return Ok(None);
+    };
+
+    let moved_users: Vec<i32> = merge.moved_rankings.iter().map(|r| r.user_id).collect();
+    let moved_ranks: Vec<i32> = merge.moved_rankings.iter().map(|r| r.rank).collect();
+    let restored_moves: Vec<i32> = sqlx::query!(
+        r#"
+        UPDATE rankings r SET song_id = $1
+        FROM UNNEST($3::int[], $4::int[]) AS moved(user_id, rank)
+        WHERE r.user_id = moved.user_id AND r.rank = moved.rank AND r.song_id = $2
+        RETURNING r.user_id
+        "#,
+        merge.from_song_id,
+        merge.into_song_id,
+        &moved_users,
+        &moved_ranks
+    )
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .map(|row| row.user_id)
+    .collect();
+
+    let dropped_users: Vec<i32> = merge.dropped_rankings.iter().map(|r| r.user_id).collect();
+    let dropped_songs: Vec<i32> = merge.dropped_rankings.iter().map(|r| r.song_id).collect();
+    let dropped_ranks: Vec<i32> = merge.dropped_rankings.iter().map(|r| r.rank).collect();
+    let restored_drops: Vec<i32> = sqlx::query!(
+        r#"
+        INSERT INTO rankings (user_id, song_id, rank)
+        SELECT * FROM UNNEST($1::int[], $2::int[], $3::int[])
+        ON CONFLICT DO NOTHING
+        RETURNING user_id
+        "#,
+        &dropped_users,
+        &dropped_songs,
+        &dropped_ranks
+    )
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .map(|row| row.user_id)
+    .collect();
+
+    // Predictions are put back the same way. They aren't votes, so they aren't counted.
+    let moved_polls: Vec<i32> = merge.moved_predictions.iter().map(|p| p.poll_id).collect();
+    let moved_users: Vec<i32> = merge.moved_predictions.iter().map(|p| p.user_id).collect();
+    let moved_positions: Vec<i32> = merge.moved_predictions.iter().map(|p| p.position).collect();
+    sqlx::query!(
+        r#"
+        UPDATE predictions p SET song_id = $1
+        FROM UNNEST($3::int[], $4::int[], $5::int[]) AS moved(poll_id, user_id, position)
+        WHERE p.poll_id = moved.poll_id AND p.user_id = moved.user_id
+          AND p.position = moved.position AND p.song_id = $2
+        "#,
+        merge.from_song_id,
+        merge.into_song_id,
+        &moved_polls,
+        &moved_users,
+        &moved_positions
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let dropped_polls: Vec<i32> = merge.dropped_predictions.iter().map(|p| p.poll_id).collect();
+    let dropped_users: Vec<i32> = merge.dropped_predictions.iter().map(|p| p.user_id).collect();
+    let dropped_songs: Vec<i32> = merge.dropped_predictions.iter().map(|p| p.song_id).collect();
+    let dropped_positions: Vec<i32> =
+        merge.dropped_predictions.iter().map(|p| p.position).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO predictions (poll_id, user_id, song_id, position)
+        SELECT * FROM UNNEST($1::int[], $2::int[], $3::int[], $4::int[])
+        ON CONFLICT DO NOTHING
+        "#,
+        &dropped_polls,
+        &dropped_users,
+        &dropped_songs,
+        &dropped_positions
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE songs SET canonical_id = $2 WHERE id = $1",
+        merge.from_song_id,
+        merge.previous_canonical_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "UPDATE songs SET canonical_id = $1 WHERE id = ANY($2)",
+        merge.from_song_id,
+        &merge.relinked_song_ids
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let restored = restored_moves.len() + restored_drops.len();
+    record_ballot_revisions(&mut tx, restored_moves.into_iter().chain(restored_drops)).await?;
+
+    tx.commit().await?;
+
+    Ok(Some(SongMergeUndo {
+        restored,
+        skipped: merge.moved_rankings.len() + merge.dropped_rankings.len() - restored,
+    }))
+}
+
+/// Splits a song back out of the canonical song it was folded into, and remembers the pair as
+/// rejected so automatic matching doesn't fold it again. Returns false if the song isn't folded
+/// into another song.
+pub async fn split_song(pool: &PgPool, song_id: &i32, split_by: &str) -> Result<bool, sqlx::Error> {
+    let mut tx: Transaction<'_, Postgres> = pool.begin().await?;
+
+    let song = sqlx::query!(
+        r#"
+        UPDATE songs new SET canonical_id = NULL
+        FROM songs old
+        WHERE new.id = $1 AND old.id = new.id AND old.canonical_id IS NOT NULL
+        RETURNING old.canonical_id AS "canonical_id!"
+        "#,
+        song_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some(song) = song else {
+        return Ok(false);
+    };
+
+    sqlx::query!(
+        r#"
+        INSERT INTO song_merge_suggestions (song_id, canonical_id, reason, status, reviewed_at, reviewed_by)
+        VALUES ($1, $2, 'manual', 'rejected', NOW(), $3)
+        ON CONFLICT (song_id, canonical_id) DO UPDATE SET
+            status = 'rejected',
+            reviewed_at = NOW(),
+            reviewed_by = EXCLUDED.reviewed_by
+        "#,
+        song_id,
+        song.canonical_id,
+        split_by
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+pub async fn get_ballot_status(
+    pool: &PgPool,
+    user_id: &i32,
+) -> Result<Option<BallotStatus>, sqlx::Error> {
+    let row = sqlx::query_as!(
+        BallotStatus,
+        r#"
+        SELECT b.status, b.version, b.updated_at, b.submitted_at,
+            (SELECT COUNT(*) FROM rankings r WHERE r.user_id = b.user_id) AS "songs!"
+        FROM ballots b
+        WHERE b.user_id = $1
+        "#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row)
+}
+
+// Submitting again just moves submitted_at forward. Returns false if the user has no ballot.
+pub async fn set_ballot_submitted(
+    pool: &PgPool,
+    user_id: &i32,
+    submitted: bool,
+) -> Result<bool, sqlx::Error> {
+    let updated = sqlx::query!(
+        r#"
+        UPDATE ballots SET
+            status = CASE WHEN $2 THEN 'submitted' ELSE 'draft' END,
+            submitted_at = CASE WHEN $2 THEN NOW() ELSE NULL END
+        WHERE user_id = $1
+        "#,
+        user_id,
+        submitted
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    Ok(updated > 0)
+}
+
+// Every user, with those who never saved a ballot counted as not yet submitted
+pub async fn get_ballot_submissions(pool: &PgPool) -> Result<BallotSubmissions, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT COALESCE(u.nickname, u.display_name, u.name) AS "label!", b.status AS "status?"
+        FROM users u
+        LEFT JOIN ballots b ON b.user_id = u.id
+        ORDER BY 1, u.id
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut submissions = BallotSubmissions::default();
+    for row in rows {
+        if row.status.as_deref() == Some("submitted") {
+            submissions.submitted_voters.push(row.label);
+        } else {
+            submissions.draft_voters.push(row.label);
+        }
+    }
+    submissions.submitted = submissions.submitted_voters.len();
+    submissions.drafts = submissions.draft_voters.len();
+
+    Ok(submissions)
+}
+
+struct BallotRevisionRow {
+    version: i32,
+    poll_id: Option<i32>,
+    status: String,
+    songs: Json<Vec<RevisionEntry>>,
+    created_at: DateTime<Utc>,
+}
+
+impl From<BallotRevisionRow> for BallotRevision {
+    fn from(row: BallotRevisionRow) -> Self {
+        BallotRevision {
+            version: row.version,
+            poll_id: row.poll_id,
+            status: row.status,
+            songs: row.songs.0,
+            created_at: row.created_at,
+        }
+    }
+}
+
+// Newest revision first
+pub async fn get_ballot_revisions(
+    pool: &PgPool,
+    user_id: &i32,
+) -> Result<Vec<BallotRevision>, sqlx::Error> {
+    let rows = sqlx::query_as!(
+        BallotRevisionRow,
+        r#"
+        SELECT version, poll_id, status, songs AS "songs: Json<Vec<RevisionEntry>>", created_at
+        FROM ballot_revisions
+        WHERE user_id = $1
+        ORDER BY version DESC
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(BallotRevision::from).collect())
+}
+
+pub async fn run_migrations(pool: &PgPool) -> Result<(), MigrateError> {
+    MIGRATOR.run(pool).await
+}
+
+/// Compares the migrations built into this binary with the ones recorded in the database.
+/// Anything other than every migration "applied" means the schema doesn't match db.rs.
+pub async fn get_migration_status(pool: &PgPool) -> Result<Vec<MigrationStatus>, MigrateError> {
+    let mut conn = pool.acquire().await?;
+    conn.ensure_migrations_table().await?;
+    let mut applied: HashMap<i64, Vec<u8>> = conn
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|migration| (migration.version, migration.checksum.into_owned()))
+        .collect();
+
+    let mut statuses: Vec<MigrationStatus> = MIGRATOR
+        .iter()
+        .filter(|migration| migration.migration_type.is_up_migration())
+        .map(|migration| {
+            let state = match applied.remove(&migration.version) {
+                None => "pending",
+                Some(checksum) if checksum != *migration.checksum => "modified",
+                Some(_) => "applied",
+            };
+            MigrationStatus {
+                version: migration.version,
+                description: migration.description.to_string(),
+                state: state.to_string(),
+            }
+        })
+        .collect();
+
+    // Applied by a newer build than this one
+    statuses.extend(applied.into_keys().map(|version| MigrationStatus {
+        version,
+        description: String::new(),
+        state: "unknown".to_string(),
+    }));
+    statuses.sort_by_key(|status| status.version);
+
+    Ok(statuses)
+}
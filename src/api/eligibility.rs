@@ -0,0 +1,90 @@
+use crate::api::types::{Poll, Song};
+use chrono::NaiveDate;
+use std::collections::HashSet;
+
+pub enum Eligibility {
+    Eligible,
+    // Saved, but reported back so the voter can double check it
+    Flagged(String),
+    Ineligible(String),
+}
+
+// The first and last day a Spotify release date could mean, given its precision
+fn release_range(release_date: &str, precision: Option<&str>) -> Option<(NaiveDate, NaiveDate)> {
+    let mut parts = release_date.split('-');
+    let year: i32 = parts.next()?.parse().ok()?;
+    let month: Option<u32> = parts.next().and_then(|month| month.parse().ok());
+    let day: Option<u32> = parts.next().and_then(|day| day.parse().ok());
+
+    match (precision.unwrap_or("day"), month, day) {
+        ("day", Some(month), Some(day)) => {
+            let date = NaiveDate::from_ymd_opt(year, month, day)?;
+            Some((date, date))
+        }
+        ("day" | "month", Some(month), _) => {
+            let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+            let next_month = if month == 12 {
+                NaiveDate::from_ymd_opt(year + 1, 1, 1)?
+            } else {
+                NaiveDate::from_ymd_opt(year, month + 1, 1)?
+            };
+            Some((first, next_month.pred_opt()?))
+        }
+        _ => Some((
+            NaiveDate::from_ymd_opt(year, 1, 1)?,
+            NaiveDate::from_ymd_opt(year, 12, 31)?,
+        )),
+    }
+}
+
+/// Checks a song's release date against the poll's eligibility window. The song should carry
+/// the release date on record or from Spotify, never one the client sent. Songs clearly
+/// outside the window, or with no date to check, are ineligible, while a year/month precision
+/// date that straddles an edge of the window is flagged rather than rejected.
+pub fn check_song(song: &Song, poll: &Poll, overrides: &HashSet<String>) -> Eligibility {
+    if poll.eligible_from.is_none() && poll.eligible_to.is_none() {
+        return Eligibility::Eligible;
+    }
+    if overrides.contains(&song.uri) {
+        return Eligibility::Eligible;
+    }
+
+    let Some(release_date) = song.release_date.as_deref() else {
+        return Eligibility::Ineligible(
+            "No release date to check eligibility against".to_string(),
+        );
+    };
+    let Some((earliest, latest)) =
+        release_range(release_date, song.release_date_precision.as_deref())
+    else {
+        return Eligibility::Ineligible(format!("Unrecognised release date {}", release_date));
+    };
+
+    let window_from = poll.eligible_from.unwrap_or(NaiveDate::MIN);
+    let window_to = poll.eligible_to.unwrap_or(NaiveDate::MAX);
+
+    if latest < window_from || earliest > window_to {
+        Eligibility::Ineligible(format!(
+            "Released {} which is outside {}",
+            release_date,
+            describe_window(poll)
+        ))
+    } else if earliest < window_from || latest > window_to {
+        Eligibility::Flagged(format!(
+            "Released {} which may be outside {}",
+            release_date,
+            describe_window(poll)
+        ))
+    } else {
+        Eligibility::Eligible
+    }
+}
+
+fn describe_window(poll: &Poll) -> String {
+    match (poll.eligible_from, poll.eligible_to) {
+        (Some(from), Some(to)) => format!("{} to {}", from, to),
+        (Some(from), None) => format!("on or after {}", from),
+        (None, Some(to)) => format!("on or before {}", to),
+        (None, None) => "any date".to_string(),
+    }
+}
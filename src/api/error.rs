@@ -0,0 +1,107 @@
+use crate::api::types::{BallotError, ErrorResponse};
+use rocket::http::Status;
+use rocket::request::Request;
+use rocket::response::{self, Responder};
+use rocket::serde::json::Json;
+use std::fmt;
+
+/// Every way a request can fail. Each variant maps to one HTTP status and a machine-readable
+/// `code` in the JSON body, so clients can branch on the code rather than the message.
+#[derive(Debug)]
+pub enum AppError {
+    /// No session, or no Spotify login where one is needed
+    Unauthenticated(String),
+    /// Logged in, but not allowed to do this, e.g. not an admin
+    Forbidden(String),
+    /// The request is malformed or out of range
+    Validation(String),
+    /// A ballot that breaks the poll's rules, with every rule it breaks
+    InvalidBallot(String, Vec<BallotError>),
+    NotFound(String),
+    /// The request clashes with the current state, e.g. a poll is already open
+    Conflict(String),
+    /// An If-Match version that is no longer the current one
+    StaleVersion(String),
+    /// Spotify failed or answered with something we couldn't use
+    Upstream(String),
+    Database(sqlx::Error),
+    /// Anything else that is our fault, e.g. missing configuration
+    Internal(String),
+}
+
+impl AppError {
+    pub fn status(&self) -> Status {
+        match self {
+            AppError::Unauthenticated(_) => Status::Unauthorized,
+            AppError::Forbidden(_) => Status::Forbidden,
+            AppError::Validation(_) => Status::BadRequest,
+            AppError::InvalidBallot(..) => Status::UnprocessableEntity,
+            AppError::NotFound(_) => Status::NotFound,
+            AppError::Conflict(_) => Status::Conflict,
+            AppError::StaleVersion(_) => Status::PreconditionFailed,
+            AppError::Upstream(_) => Status::BadGateway,
+            AppError::Database(_) | AppError::Internal(_) => Status::InternalServerError,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Unauthenticated(_) => "unauthenticated",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::Validation(_) => "invalid_request",
+            AppError::InvalidBallot(..) => "invalid_ballot",
+            AppError::NotFound(_) => "not_found",
+            AppError::Conflict(_) => "conflict",
+            AppError::StaleVersion(_) => "stale_version",
+            AppError::Upstream(_) => "spotify_error",
+            AppError::Database(_) => "database_error",
+            AppError::Internal(_) => "internal_error",
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Unauthenticated(message)
+            | AppError::Forbidden(message)
+            | AppError::Validation(message)
+            | AppError::InvalidBallot(message, _)
+            | AppError::NotFound(message)
+            | AppError::Conflict(message)
+            | AppError::StaleVersion(message)
+            | AppError::Upstream(message)
+            | AppError::Internal(message) => f.write_str(message),
+            // The details stay in the log
+            AppError::Database(_) => f.write_str("Database error"),
+        }
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(err: sqlx::Error) -> Self {
+        AppError::Database(err)
+    }
+}
+
+impl<'r> Responder<'r, 'static> for AppError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        match &self {
+            AppError::Database(err) => rocket::error!("Database error: {}", err),
+            AppError::Upstream(message) => rocket::error!("Spotify error: {}", message),
+            AppError::Internal(message) => rocket::error!("{}", message),
+            _ => {}
+        }
+
+        let status = self.status();
+        let body = ErrorResponse {
+            code: self.code(),
+            error: self.to_string(),
+            errors: match self {
+                AppError::InvalidBallot(_, errors) => errors,
+                _ => Vec::new(),
+            },
+        };
+        (status, Json(body)).respond_to(request)
+    }
+}
@@ -1,269 +1,223 @@
-use crate::api::internal_api::{login_page, main_page};
+use crate::api::db;
+use crate::api::error::AppError;
+use crate::api::search_cache::{SearchCache, SearchKey, SEARCH_CACHE_MAX_ENTRIES, SEARCH_CACHE_TTL};
+use crate::api::search_query::{
+    build_search_query, parse_count, parse_market, parse_track_link, valid_year,
+    DEFAULT_SEARCH_LIMIT, MAX_SEARCH_LIMIT, MAX_SEARCH_OFFSET,
+};
+use crate::api::session::SessionUser;
 use crate::api::types::{
-    AccessTokenResponse, AddSongsToPlaylistBody, CreatePlaylistBody,
-    ErrorResponse, SearchSongsQuery, Song,
+    AccessTokenResponse, AddSongsToPlaylistBody, CreatePlaylistBody, CreatePlaylistId, SearchPage,
+    SearchSongsQuery, Song, TrackArtist, TrackImage, TrackMetadata,
 };
 use reqwest::Client;
-use rocket::http::{Cookie, CookieJar, Status};
+use rocket::http::{Cookie, CookieJar};
 use rocket::response::Redirect;
 use rocket::serde::json::Json;
 use rocket::State;
 use rocket::time::Duration;
+use rocket::tokio::sync::Mutex;
 use rocket::tokio::time::sleep;
 use std::collections::HashSet;
 use std::env;
-use std::time::Duration as StdDuration;
+use std::sync::{LazyLock, Mutex as StdMutex, MutexGuard, PoisonError};
+use std::time::{Duration as StdDuration, Instant};
 
 static SPOTIFY_AUTH_URL: &str = "https://accounts.spotify.com/authorize";
 static SPOTIFY_TOKEN_URL: &str = "https://accounts.spotify.com/api/token";
+static SPOTIFY_PROFILE_URL: &str = "https://api.spotify.com/v1/me";
+static SPOTIFY_TRACKS_URL: &str = "https://api.spotify.com/v1/tracks";
+static SPOTIFY_SEARCH_URL: &str = "https://api.spotify.com/v1/search";
+
+// The /v1/tracks endpoint takes at most 50 IDs per call
+const TRACKS_BATCH_SIZE: usize = 50;
+// The size the ballot UI is designed around
+const COVER_WIDTH: i32 = 300;
+// Spotify tokens last an hour, and say so in expires_in
+const DEFAULT_TOKEN_SECONDS: u64 = 3600;
+// Refresh the app token this long before it expires so requests never race its expiry
+const APP_TOKEN_REFRESH_MARGIN: StdDuration = StdDuration::from_secs(60);
 
 pub async fn authenticate() -> Redirect {
-    let client_id = env::var("SPOTIFY_CLIENT");
-    if client_id.is_err() {
-        Redirect::to("/fail");
-    }
-
-    let redirect_uri = env::var("SPOTIFY_REDIRECT_URI").expect("SPOTIFY_REDIRECT_URI must be set");
+    let (Ok(client_id), Ok(redirect_uri)) =
+        (env::var("SPOTIFY_CLIENT"), env::var("SPOTIFY_REDIRECT_URI"))
+    else {
+        rocket::error!("SPOTIFY_CLIENT and SPOTIFY_REDIRECT_URI must be set");
+        return Redirect::to("/fail");
+    };
 
     let auth_url = format!(
         "{}?client_id={}&response_type=code&redirect_uri={}&scope=playlist-modify-public%20user-read-private&state=random_state_string",
         SPOTIFY_AUTH_URL,
-        client_id.unwrap(),
+        client_id,
         urlencoding::encode(&redirect_uri)
     );
     Redirect::to(auth_url)
 }
 
-#[get("/callback?<code>")]
-pub async fn callback(cookies: &CookieJar<'_>, code: String) -> Redirect {
-    let client_id = env::var("SPOTIFY_CLIENT");
-    if client_id.is_err() {
-        Redirect::to("/fail");
-    }
+// Spotify sends `error` instead of `code` when the user declines
+#[get("/callback?<code>&<error>")]
+pub async fn callback(
+    cookies: &CookieJar<'_>,
+    code: Option<String>,
+    error: Option<String>,
+) -> Redirect {
+    let Some(code) = code.filter(|code| !code.is_empty()) else {
+        rocket::error!(
+            "Spotify login failed: {}",
+            error.as_deref().unwrap_or("no code in the callback")
+        );
+        return Redirect::to("/fail");
+    };
 
-    let client_secret = env::var("SPOTIFY_SECRET");
-    if client_secret.is_err() {
-        Redirect::to("/fail");
+    if let Err(err) = log_in_with_spotify(cookies, &code).await {
+        rocket::error!("Spotify login failed: {:?}", err);
+        return Redirect::to("/fail");
     }
 
-    let redirect_uri = env::var("SPOTIFY_REDIRECT_URI").expect("SPOTIFY_REDIRECT_URI must be set");
+    sleep(StdDuration::from_secs(3)).await;
+    Redirect::to("/main")
+}
+
+// Swaps the authorisation code for the user's token, saves their profile and starts a session
+async fn log_in_with_spotify(cookies: &CookieJar<'_>, code: &str) -> Result<(), AppError> {
+    let (Ok(client_id), Ok(client_secret), Ok(redirect_uri)) = (
+        env::var("SPOTIFY_CLIENT"),
+        env::var("SPOTIFY_SECRET"),
+        env::var("SPOTIFY_REDIRECT_URI"),
+    ) else {
+        return Err(AppError::Internal(
+            "SPOTIFY_CLIENT, SPOTIFY_SECRET and SPOTIFY_REDIRECT_URI must be set".to_string(),
+        ));
+    };
 
     let client = Client::new();
     let response = client
         .post(SPOTIFY_TOKEN_URL)
-        .header("Content-Type", "application/x-www-form-urlencoded")
         .form(&[
             ("grant_type", "authorization_code"),
-            ("code", &code),
+            ("code", code),
             ("redirect_uri", &redirect_uri),
-            ("client_id", &client_id.unwrap()),
-            ("client_secret", &client_secret.unwrap()),
+            ("client_id", &client_id),
+            ("client_secret", &client_secret),
         ])
         .send()
         .await
-        .expect("Failed to get access token");
-
+        .map_err(|err| AppError::Upstream(format!("Failed to get access token: {}", err)))?;
+    if !response.status().is_success() {
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(AppError::Upstream(format!("Spotify token error: {}", error_text)));
+    }
     let data: AccessTokenResponse = response
         .json()
         .await
-        .expect("Failed to parse token response");
-
-    let profile_url = "https://api.spotify.com/v1/me";
+        .map_err(|err| AppError::Upstream(format!("Failed to parse token response: {}", err)))?;
 
     let response = client
-        .get(profile_url)
-        .header(
-            "Authorization",
-            format!("Bearer {}", data.access_token.clone()),
-        )
+        .get(SPOTIFY_PROFILE_URL)
+        .header("Authorization", format!("Bearer {}", data.access_token))
         .send()
         .await
-        .expect("Failed to parse token response");
-
-    match response {
-        res if res.status().is_success() => {
-            let data = &res
-                .json::<serde_json::Value>()
-                .await
-                .expect("Failed to get access token");
-            rocket::info!("Data {:#?}", data);
-            cookies.add_private(
-                Cookie::build(("user", data["uri"].to_string()))
-                    .http_only(true)
-                    .secure(true)
-                    .max_age(Duration::minutes(60)),
-            )
-        }
-        res => {
-            // Handle other HTTP statuses
-            rocket::error!("Response was not successful: {:?}", res.status());
-            return Redirect::to("/fail");
-        }
+        .map_err(|err| AppError::Upstream(format!("Failed to get profile: {}", err)))?;
+    if !response.status().is_success() {
+        return Err(AppError::Upstream(format!(
+            "Profile request failed with {}",
+            response.status()
+        )));
     }
-
+    let profile = response
+        .json::<serde_json::Value>()
+        .await
+        .map_err(|err| AppError::Upstream(format!("Failed to parse profile: {}", err)))?;
+
+    let provider_user_id = profile["id"]
+        .as_str()
+        .ok_or_else(|| AppError::Upstream("Profile response has no user ID".to_string()))?;
+    let session = SessionUser::spotify(provider_user_id);
+    let display_name = profile["display_name"].as_str().filter(|name| !name.is_empty());
+    let avatar_url = profile["images"]
+        .as_array()
+        .and_then(|images| images.first())
+        .and_then(|image| image["url"].as_str());
+    db::save_user_profile(
+        db::pool()?,
+        &session.provider,
+        &session.provider_user_id,
+        &session.name(),
+        display_name,
+        avatar_url,
+    )
+    .await?;
+
+    session.save(cookies);
     cookies.add_private(
         Cookie::build(("api_token", data.access_token))
             .http_only(true)
             .secure(true)
             .max_age(Duration::minutes(60)),
     );
-
-    sleep(StdDuration::from_secs(3)).await;
-    Redirect::to("/main")
+    Ok(())
 }
 
 pub async fn create_playlist(
     cookies: &CookieJar<'_>,
     client: &State<Client>,
-) -> Result<String, (Status, Json<ErrorResponse>)> {
-    let user_name_opt = cookies
-        .get_private("user")
-        .map(|cookie| cookie.value().to_string());
-
-    if user_name_opt.is_none() {
-        return Err((
-            Status::InternalServerError,
-            Json(ErrorResponse {
-                error: "No Token".to_string(),
-            }),
+    name: &str,
+    description: &str,
+) -> Result<String, AppError> {
+    let session = SessionUser::require(cookies)?;
+    if session.provider != "spotify" {
+        return Err(AppError::Forbidden(
+            "Playlists can only be created for Spotify accounts".to_string(),
         ));
     }
-
-    let user_name = user_name_opt.unwrap();
-
-    let split_name = user_name.split(':').nth(2).ok_or_else(|| {
-        (
-            Status::InternalServerError,
-            Json(ErrorResponse {
-                error: "Failed to parse Username to parse into Spotify API".to_string(),
-            }),
-        )
-    })?;
-
-    let split_name_trimmed = split_name.trim().replace('\\', "").replace('\"', "");
-
-    let access_token_opt = cookies
-        .get_private("api_token")
-        .map(|cookie| cookie.value().to_string());
-    if access_token_opt.is_none() {
-        return Err((
-            Status::InternalServerError,
-            Json(ErrorResponse {
-                error: "No Token".to_string(),
-            }),
-        ));
-    }
-
-    let access_token = access_token_opt.unwrap();
-
-    let json_body = serde_json::to_string(&CreatePlaylistBody {
-        name: "Hottest100".to_string(),
-        description: "Hottest100".to_string(),
-        public: true,
-    })
-    .map_err(|err| {
-        (
-            Status::InternalServerError,
-            Json(ErrorResponse {
-                error: format!(
-                    "Failed to parse Username to parse into Spotify API: {}",
-                    err
-                ),
-            }),
-        )
-    })?;
+    let access_token = user_access_token(cookies)?;
 
     let create_spotify_playlist = format!(
         "https://api.spotify.com/v1/users/{}/playlists",
-        split_name_trimmed
+        urlencoding::encode(&session.provider_user_id)
     );
 
-    rocket::info!("JSON {:#?}", json_body);
-
     rocket::info!("URL {:#?}", create_spotify_playlist);
 
     let response = client
         .post(&create_spotify_playlist)
-        .body(json_body)
+        .json(&CreatePlaylistBody {
+            name: name.to_string(),
+            description: description.to_string(),
+            public: true,
+        })
         .header("Authorization", format!("Bearer {}", access_token))
         .send()
         .await
-        .map_err(|err| {
-            (
-                Status::InternalServerError,
-                Json(ErrorResponse {
-                    error: format!("Failed to create Playlist via Spotify API: {}", err),
-                }),
-            )
-        })?;
+        .map_err(|err| AppError::Upstream(format!("Failed to create playlist: {}", err)))?;
 
-    if response.status().is_success() {
-        if let Ok(data) = response.json::<serde_json::Value>().await {
-            let id = &data["id"];
-            // Process the `id` here
-            Ok(id.to_string())
-        } else {
-            Err((
-                Status::InternalServerError,
-                Json(ErrorResponse {
-                    error: "Failed to parse Spotify API response".to_string(),
-                }),
-            ))
-        }
-    } else {
-        if let Ok(data) = response.json::<serde_json::Value>().await {
-            rocket::error!("Failed: {}", data);
-            Err((
-                Status::InternalServerError,
-                Json(ErrorResponse {
-                    error: format!("Failed to post to Spotify API - {}", data),
-                }),
-            ))
-        } else {
-            Err((
-                Status::InternalServerError,
-                Json(ErrorResponse {
-                    error: "Failed to parse Spotify API response".to_string(),
-                }),
-            ))
-        }
+    if !response.status().is_success() {
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(AppError::Upstream(format!("Failed to create playlist: {}", error_text)));
     }
+
+    response
+        .json::<CreatePlaylistId>()
+        .await
+        .map(|data| data.id)
+        .map_err(|err| AppError::Upstream(format!("Failed to parse Spotify API response: {}", err)))
 }
+
 pub async fn add_songs_to_playlist(
     create_playlist_id: String,
     ranked_song_uris: Vec<String>,
     cookies: &CookieJar<'_>,
     client: &State<Client>,
-) -> Result<(), (Status, Json<ErrorResponse>)> {
-    let access_token_opt = cookies
-        .get_private("api_token")
-        .map(|cookie| cookie.value().to_string());
-    if access_token_opt.is_none() {
-        return Err((
-            Status::InternalServerError,
-            Json(ErrorResponse {
-                error: "No Access Token".to_string(),
-            }),
-        ));
-    }
-
-    let access_token = access_token_opt.unwrap();
+) -> Result<(), AppError> {
+    let access_token = user_access_token(cookies)?;
 
     let add_songs_to_playlist = AddSongsToPlaylistBody {
         uris: ranked_song_uris,
         position: 0,
     };
 
-    let json_body = serde_json::to_string(&add_songs_to_playlist).map_err(|err| {
-        (
-            Status::InternalServerError,
-            Json(ErrorResponse {
-                error: format!("Failed to parse ranked songs into JSON: {}", err),
-            }),
-        )
-    })?;
-
-    rocket::info!("Songs {:#?}", json_body);
+    rocket::info!("Songs {:#?}", add_songs_to_playlist.uris);
 
     let playlist_id = create_playlist_id.trim_matches('"');
 
@@ -276,20 +230,12 @@ pub async fn add_songs_to_playlist(
 
     let response = client
         .post(&create_spotify_playlist)
-        .body(json_body)
+        .json(&add_songs_to_playlist)
         .header("Authorization", format!("Bearer {}", access_token))
         .send()
         .await
         .map_err(|err| {
-            (
-                Status::InternalServerError,
-                Json(ErrorResponse {
-                    error: format!(
-                        "Failed to add ranked songs to playlist via Spotify API: {}",
-                        err
-                    ),
-                }),
-            )
+            AppError::Upstream(format!("Failed to add ranked songs to playlist: {}", err))
         })?;
 
     if response.status().is_success() {
@@ -299,54 +245,162 @@ pub async fn add_songs_to_playlist(
             .text()
             .await
             .unwrap_or_else(|e| format!("Failed to read response: {}", e));
-        Err((
-            Status::InternalServerError,
-            Json(ErrorResponse {
-                error: format!("Failed to post to Spotify API: {}", error_text),
-            }),
-        ))
+        Err(AppError::Upstream(format!("Failed to add ranked songs to playlist: {}", error_text)))
     }
 }
 
-#[get("/search-songs?<query..>")]
+// The logged in user's own Spotify token, which playlist changes are made with
+fn user_access_token(cookies: &CookieJar<'_>) -> Result<String, AppError> {
+    cookies
+        .get_private("api_token")
+        .map(|cookie| cookie.value().to_string())
+        .ok_or_else(|| {
+            AppError::Unauthenticated("Log in with Spotify again to create playlists".to_string())
+        })
+}
+
+struct AppToken {
+    access_token: String,
+    refresh_at: Instant,
+}
+
+// Shared by every request, and held while refreshing so concurrent requests wait for one fetch
+static APP_TOKEN: Mutex<Option<AppToken>> = Mutex::const_new(None);
+
+/// The app-level token from the client credentials flow, used for catalogue search and metadata
+/// backfill. It acts for no user, so it works for guests and after a user's own token expires.
+/// Cached until shortly before it expires.
+pub async fn app_access_token(client: &Client) -> Result<String, AppError> {
+    let mut cached = APP_TOKEN.lock().await;
+    if let Some(token) = cached.as_ref().filter(|token| token.refresh_at > Instant::now()) {
+        return Ok(token.access_token.clone());
+    }
+
+    let data = request_app_token(client).await?;
+    let lifetime = StdDuration::from_secs(data.expires_in.unwrap_or(DEFAULT_TOKEN_SECONDS));
+    let token = AppToken {
+        access_token: data.access_token,
+        refresh_at: Instant::now() + lifetime.saturating_sub(APP_TOKEN_REFRESH_MARGIN),
+    };
+    let access_token = token.access_token.clone();
+    *cached = Some(token);
+    rocket::info!("Fetched a new app access token, valid for {:?}", lifetime);
+    Ok(access_token)
+}
+
+async fn request_app_token(client: &Client) -> Result<AccessTokenResponse, AppError> {
+    let (Ok(client_id), Ok(client_secret)) =
+        (env::var("SPOTIFY_CLIENT"), env::var("SPOTIFY_SECRET"))
+    else {
+        return Err(AppError::Internal(
+            "SPOTIFY_CLIENT and SPOTIFY_SECRET must be set".to_string(),
+        ));
+    };
+
+    let response = client
+        .post(SPOTIFY_TOKEN_URL)
+        .basic_auth(client_id, Some(client_secret))
+        .form(&[("grant_type", "client_credentials")])
+        .send()
+        .await
+        .map_err(|err| AppError::Upstream(format!("Failed to get app access token: {}", err)))?;
+    if !response.status().is_success() {
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(AppError::Upstream(format!("Spotify token error: {}", error_text)));
+    }
+
+    response
+        .json()
+        .await
+        .map_err(|err| AppError::Upstream(format!("Failed to parse token response: {}", err)))
+}
+
+static SEARCH_CACHE: LazyLock<StdMutex<SearchCache>> =
+    LazyLock::new(|| StdMutex::new(SearchCache::new(SEARCH_CACHE_TTL, SEARCH_CACHE_MAX_ENTRIES)));
+
+// A panic mid-update can't leave the cache inconsistent, so a poisoned lock is still usable
+fn lock_search_cache() -> MutexGuard<'static, SearchCache> {
+    SEARCH_CACHE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Searches Spotify for tracks, or looks up a pasted track link. Pages are cached for
+/// `SEARCH_CACHE_TTL`.
 pub async fn search_spotify_songs(
-    cookies: &CookieJar<'_>,
     query: Option<SearchSongsQuery>,
     client: &State<Client>,
-) -> Result<Json<Vec<Song>>, (Status, Json<ErrorResponse>)> {
-    let query = query.unwrap();
+) -> Result<Json<SearchPage>, AppError> {
+    let bad_request = AppError::Validation;
+    let Some(query) = query else {
+        return Err(bad_request("Search needs a track".to_string()));
+    };
 
-    let token = cookies
-        .get_private("api_token")
-        .map(|cookie| cookie.value().to_string());
-
-    if token.is_none() {
-        login_page().await;
-        let token2 = cookies
-            .get_private("api_token")
-            .map(|cookie| cookie.value().to_string());
-        if token2.is_none() {
-            return Err((
-                Status::InternalServerError,
-                Json(ErrorResponse {
-                    error: "No Token".to_string(),
-                }),
-            ));
-        }
+    let text = query.track.as_deref().unwrap_or_default();
+    let rank = query.rank;
+    let with_rank = |page: SearchPage| SearchPage {
+        songs: page
+            .songs
+            .into_iter()
+            .map(|song| Song { rank, ..song })
+            .collect(),
+        ..page
+    };
+
+    // A pasted link names the track exactly, so skip the search
+    if let Some(id) = parse_track_link(text) {
+        let access_token = app_access_token(client).await?;
+        let songs = get_tracks(client, &access_token, &[format!("spotify:track:{}", id)]).await?;
+        let total = songs.len() as u32;
+        return Ok(Json(with_rank(SearchPage {
+            songs,
+            offset: 0,
+            limit: total,
+            total,
+            next_offset: None,
+        })));
     }
 
-    let access_token = token.unwrap();
+    let limit = parse_count(query.limit.as_deref(), DEFAULT_SEARCH_LIMIT)
+        .filter(|limit| (1..=MAX_SEARCH_LIMIT).contains(limit))
+        .ok_or_else(|| bad_request(format!("limit must be from 1 to {}", MAX_SEARCH_LIMIT)))?;
+    let offset = parse_count(query.offset.as_deref(), 0)
+        .filter(|offset| *offset <= MAX_SEARCH_OFFSET)
+        .ok_or_else(|| bad_request(format!("offset must be from 0 to {}", MAX_SEARCH_OFFSET)))?;
+    // SPOTIFY_MARKET is the default for the group, since the app token has no country of its own
+    let market = match query.market.clone().or_else(|| env::var("SPOTIFY_MARKET").ok()) {
+        Some(market) => Some(
+            parse_market(&market)
+                .ok_or_else(|| bad_request(format!("Unknown market {}", market)))?,
+        ),
+        None => None,
+    };
+    if let Some(year) = query.year.as_deref().filter(|year| !valid_year(year.trim())) {
+        return Err(bad_request(format!("Year {} should look like 2024 or 2020-2024", year)));
+    }
 
-    // Extract the track name from the query
-    let track_name = query.track.unwrap();
-    let rank = query.rank.unwrap();
+    let search = build_search_query(text, query.artist.as_deref(), query.year.as_deref());
+    if search.is_empty() {
+        return Err(bad_request("Search needs a track, artist or year".to_string()));
+    }
 
-    let spotify_url = format!(
-        "https://api.spotify.com/v1/search?q={}&type=track&limit=10",
-        track_name
-    );
+    let key = SearchKey::new(&search, market.as_deref(), offset, limit);
+    if let Some(page) = lock_search_cache().get(&key) {
+        return Ok(Json(with_rank(page)));
+    }
+
+    let access_token = app_access_token(client).await?;
+
+    let mut params = vec![
+        ("q", search.clone()),
+        ("type", "track".to_string()),
+        ("limit", limit.to_string()),
+        ("offset", offset.to_string()),
+    ];
+    if let Some(market) = &market {
+        params.push(("market", market.clone()));
+    }
     let response = client
-        .get(&spotify_url)
+        .get(SPOTIFY_SEARCH_URL)
+        .query(&params)
         .header("Authorization", format!("Bearer {}", access_token))
         .send()
         .await;
@@ -354,63 +408,157 @@ pub async fn search_spotify_songs(
     match response {
         Ok(res) if res.status().is_success() => {
             let data = res.json::<serde_json::Value>().await.map_err(|err| {
-                (
-                    Status::InternalServerError,
-                    Json(ErrorResponse {
-                        error: format!("Failed to parse Spotify API response: {}", err),
-                    }),
-                )
+                AppError::Upstream(format!("Failed to parse Spotify API response: {}", err))
             })?;
 
-            let items = data["tracks"]["items"].as_array();
+            let items = data["tracks"]["items"].as_array().cloned().unwrap_or_default();
+            let total = data["tracks"]["total"]
+                .as_u64()
+                .and_then(|total| u32::try_from(total).ok())
+                .unwrap_or_default();
 
             let mut seen_keys = HashSet::new();
             let songs: Vec<Song> = items
-                .unwrap()
-                .to_vec()
-                .into_iter()
-                .filter_map(|item| {
-                    let name = item["name"].as_str()?.to_string();
-                    let artist = item["artists"][0]["name"].as_str()?.to_string();
-                    let key = format!("{}{}", name, artist);
-
-                    // Skip if the key is a duplicate
-                    if !seen_keys.insert(key.clone()) {
-                        return None;
-                    }
-
-                    Some(Song {
-                        key: Some(key),
-                        name,
-                        artist,
-                        uri: item["uri"].as_str().unwrap_or_default().to_string(),
-                        album_cover_url: item["album"]["images"][1]["url"]
-                            .as_str()
-                            .unwrap_or_default()
-                            .to_string(),
-                        rank: Some(rank),
-                    })
-                })
+                .iter()
+                .filter_map(|item| parse_track(item, None))
+                // Skip if the key is a duplicate
+                .filter(|song| seen_keys.insert(song.key.clone()))
                 .collect();
 
-            rocket::info!("Tracks {:#?}", songs);
-
-            Ok(Json(songs))
+            let next_offset = Some(offset + limit)
+                .filter(|next| *next < total && *next <= MAX_SEARCH_OFFSET);
+            let page = SearchPage {
+                songs,
+                offset,
+                limit,
+                total,
+                next_offset,
+            };
+            lock_search_cache().insert(key, page.clone());
+
+            Ok(Json(with_rank(page)))
         }
         Ok(res) => {
             let error_text = res.text().await.unwrap_or_default();
-            Err((
-                Status::InternalServerError,
-                Json(ErrorResponse {
-                    error: format!("Spotify API error: {}", error_text),
-                }),
-            ))
+            Err(AppError::Upstream(format!("Spotify API error: {}", error_text)))
         }
-        Err(err) => Err((
-            Status::InternalServerError,
-            Json(ErrorResponse {
-                error: format!("Failed to call Spotify API: {}", err),
-            }),
-        )),
+        Err(err) => Err(AppError::Upstream(format!("Failed to call Spotify API: {}", err))),
     }
 }
+
+// Spotify usually returns 640, 300 and 64 pixel covers, but not always all three
+fn pick_album_cover(images: &[TrackImage]) -> String {
+    images
+        .iter()
+        .min_by_key(|image| (image.width.unwrap_or(0) - COVER_WIDTH).abs())
+        .map(|image| image.url.clone())
+        .unwrap_or_default()
+}
+
+/// Builds a Song from a Spotify track object, as returned by /v1/search and /v1/tracks.
+pub fn parse_track(item: &serde_json::Value, rank: Option<i32>) -> Option<Song> {
+    let name = item["name"].as_str()?.to_string();
+    let artist = item["artists"][0]["name"].as_str()?.to_string();
+    let to_i32 = |value: &serde_json::Value| value.as_i64().and_then(|v| i32::try_from(v).ok());
+
+    let artists = item["artists"]
+        .as_array()
+        .map(|artists| {
+            artists
+                .iter()
+                .filter_map(|artist| {
+                    Some(TrackArtist {
+                        id: artist["id"].as_str().map(String::from),
+                        name: artist["name"].as_str()?.to_string(),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let images: Vec<TrackImage> = item["album"]["images"]
+        .as_array()
+        .map(|images| {
+            images
+                .iter()
+                .filter_map(|image| {
+                    Some(TrackImage {
+                        url: image["url"].as_str()?.to_string(),
+                        width: to_i32(&image["width"]),
+                        height: to_i32(&image["height"]),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Some(Song {
+        key: Some(format!("{}{}", name, artist)),
+        uri: item["uri"].as_str().unwrap_or_default().to_string(),
+        album_cover_url: pick_album_cover(&images),
+        release_date: item["album"]["release_date"].as_str().map(String::from),
+        release_date_precision: item["album"]["release_date_precision"]
+            .as_str()
+            .map(String::from),
+        metadata: TrackMetadata {
+            isrc: item["external_ids"]["isrc"].as_str().map(String::from),
+            album_name: item["album"]["name"].as_str().map(String::from),
+            artists,
+            duration_ms: to_i32(&item["duration_ms"]),
+            explicit: item["explicit"].as_bool(),
+            popularity: to_i32(&item["popularity"]),
+            preview_url: item["preview_url"].as_str().map(String::from),
+            images,
+        },
+        name,
+        artist,
+        rank,
+    })
+}
+
+/// Looks up full track objects for a list of Spotify track URIs, 50 at a time. Tracks Spotify
+/// doesn't know about are left out of the result.
+pub async fn get_tracks(
+    client: &Client,
+    access_token: &str,
+    uris: &[String],
+) -> Result<Vec<Song>, AppError> {
+    let mut songs = Vec::new();
+
+    for batch in uris.chunks(TRACKS_BATCH_SIZE) {
+        let ids: Vec<&str> = batch
+            .iter()
+            .filter_map(|uri| uri.strip_prefix("spotify:track:"))
+            .collect();
+        if ids.is_empty() {
+            continue;
+        }
+
+        let response = client
+            .get(SPOTIFY_TRACKS_URL)
+            .query(&[("ids", ids.join(","))])
+            .header("Authorization", format!("Bearer {}", access_token))
+            .send()
+            .await
+            .map_err(|err| AppError::Upstream(format!("Failed to call Spotify API: {}", err)))?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(AppError::Upstream(format!("Spotify API error: {}", error_text)));
+        }
+
+        let data = response.json::<serde_json::Value>().await.map_err(|err| {
+            AppError::Upstream(format!("Failed to parse Spotify API response: {}", err))
+        })?;
+
+        songs.extend(
+            data["tracks"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|item| parse_track(item, None)),
+        );
+    }
+
+    Ok(songs)
+}
@@ -1,20 +1,45 @@
+use crate::api::analytics::{self, DEFAULT_CLUSTER_MIN_SCORE};
+use crate::api::ballot_editing::{self, IfMatch, Versioned};
+use crate::api::ballot_history;
+use crate::api::ballot_validation;
+use crate::api::blend;
+use crate::api::countdown_comparison;
 use crate::api::db;
+use crate::api::error::AppError;
 use crate::api::external_api::{
-    add_songs_to_playlist, authenticate, create_playlist, search_spotify_songs,
+    add_songs_to_playlist, app_access_token, authenticate, create_playlist, get_tracks,
+    search_spotify_songs,
 };
-use crate::api::types::{ErrorResponse, MusicTasteOverview, SearchSongsQuery, Song};
-use crate::DB_POOL;
+use crate::api::predictions;
+use crate::api::recommendations::{self, DEFAULT_RECOMMENDATION_LIMIT};
+use crate::api::search_query::parse_track_link;
+use crate::api::session::SessionUser;
+use crate::api::taste_graph::{self, DEFAULT_GRAPH_MIN_SCORE, DEFAULT_GRAPH_TOP_K};
+use crate::api::types::{
+    BallotDiff, BallotError, BallotRevision, BallotRules, BallotStatus, BallotSubmissions,
+    CanonicalScan, CountdownComparison, EligibilityFlag, EligibilityOverride, EligibilityWindow,
+    GraphFormat, GuestInvite, GuestInviteRequest, GuestJoinRequest, GuestLogin, GuestLoginRequest,
+    LeaderboardEntry, MergeSuggestion, MetadataBackfill, MigrationStatus, MusicTasteOverview,
+    NicknameRequest, OfficialResultEntry, OfficialResultsImport, Poll, Recommendation,
+    SaveSongsResponse, ScoringRules, SearchPage, SearchSongsQuery, Song, SongMerge,
+    SongMergeRequest, SongMergeUndo, TasteCluster, UserProfile, WrappedSummary,
+};
+use crate::api::wrapped;
+use chrono::{Duration, Utc};
 use reqwest::Client;
 use rocket::fs::NamedFile;
-use rocket::http::{CookieJar, Status};
+use rocket::http::{ContentType, CookieJar, Status};
+use rocket::request::Request;
 use rocket::response::Redirect;
 use rocket::serde::json::Json;
 use rocket::State;
+use std::collections::{HashMap, HashSet};
+use std::env;
 use std::path::{Path, PathBuf};
 
 #[get("/")]
 pub async fn index(cookies: &CookieJar<'_>) -> Result<Redirect, Redirect> {
-    if cookies.get_private("api_token").is_some() {
+    if cookies.get_private("api_token").is_some() || SessionUser::from_cookies(cookies).is_some() {
         return Ok(Redirect::to("/main"));
     }
     Ok(Redirect::to("/login"))
@@ -26,11 +51,17 @@ pub async fn login_page() -> Redirect {
     authenticate().await
 }
 
+// Where invite links point, e.g. /guest?code=AB12CD34EF. The page reads the code itself.
+#[get("/guest")]
+pub async fn guest_page() -> Option<NamedFile> {
+    NamedFile::open(Path::new("static").join("guest.html")).await.ok()
+}
+
 #[get("/main")]
 pub async fn main_page(cookies: &CookieJar<'_>) -> Result<NamedFile, Redirect> {
     let token = cookies.get_private("api_token");
     rocket::info!("Cookie check - api_token present: {}", token.is_some());
-    
+
     let mut file_path = PathBuf::from("static");
     file_path.push("index.html");
     NamedFile::open(file_path)
@@ -43,139 +74,1366 @@ pub async fn files(file: PathBuf) -> Option<NamedFile> {
     NamedFile::open(Path::new("static").join(file)).await.ok()
 }
 
+/// Gives Rocket's own failures, like a body that isn't valid JSON or a route that doesn't
+/// exist, the same JSON shape as errors from the handlers
+#[catch(default)]
+pub fn catch_error(status: Status, _request: &Request<'_>) -> AppError {
+    let reason = status.reason().unwrap_or("Unknown error").to_string();
+    match status.code {
+        400 | 422 => AppError::Validation(format!("Malformed request: {}", reason)),
+        401 => AppError::Unauthenticated(reason),
+        403 => AppError::Forbidden(reason),
+        404 => AppError::NotFound(reason),
+        _ => AppError::Internal(reason),
+    }
+}
+
+// The open poll (if any), its ballot rules and the songs an admin has declared eligible
+async fn open_poll_rules(
+    db_pool: &sqlx::PgPool,
+) -> Result<(Option<Poll>, BallotRules, HashSet<String>), sqlx::Error> {
+    let Some(poll) = db::get_open_poll(db_pool).await? else {
+        return Ok((None, BallotRules::default(), HashSet::new()));
+    };
+    let rules = db::get_ballot_rules(db_pool, &poll.id)
+        .await?
+        .unwrap_or_default();
+    let overrides = db::get_eligibility_overrides(db_pool, &poll.id)
+        .await?
+        .into_iter()
+        .collect();
+    Ok((Some(poll), rules, overrides))
+}
+
+// The songs with the release dates on record, or from Spotify for songs without one, in place
+// of whatever the client sent. Only looked up when the poll has an eligibility window.
+async fn with_known_release_dates(
+    client: &Client,
+    db_pool: &sqlx::PgPool,
+    poll: Option<&Poll>,
+    songs: &[Song],
+) -> Result<Vec<Song>, AppError> {
+    let mut songs = songs.to_vec();
+    for song in &mut songs {
+        song.release_date = None;
+        song.release_date_precision = None;
+    }
+    if !poll.is_some_and(|poll| poll.eligible_from.is_some() || poll.eligible_to.is_some()) {
+        return Ok(songs);
+    }
+
+    let uris: Vec<String> = songs.iter().map(|song| song.uri.clone()).collect();
+    let mut known: HashMap<String, (String, Option<String>)> = db::get_release_dates(db_pool, &uris)
+        .await?
+        .into_iter()
+        .map(|date| (date.uri, (date.release_date, date.release_date_precision)))
+        .collect();
+
+    let unknown: Vec<String> = uris.into_iter().filter(|uri| !known.contains_key(uri)).collect();
+    if !unknown.is_empty() {
+        let access_token = app_access_token(client).await?;
+        for track in get_tracks(client, &access_token, &unknown).await? {
+            db::update_song_metadata(db_pool, &track).await?;
+            if let Some(release_date) = track.release_date {
+                known.insert(track.uri, (release_date, track.release_date_precision));
+            }
+        }
+    }
+
+    for song in &mut songs {
+        if let Some((release_date, precision)) = known.get(&song.uri) {
+            song.release_date = Some(release_date.clone());
+            song.release_date_precision = precision.clone();
+        }
+    }
+    Ok(songs)
+}
+
+fn no_ballot() -> AppError {
+    AppError::NotFound("No ballot saved yet".to_string())
+}
+
+fn ballot_breaks_rules(errors: Vec<BallotError>) -> AppError {
+    AppError::InvalidBallot(format!("Ballot breaks {} rule(s)", errors.len()), errors)
+}
+
+// Validates and saves a whole ballot, returning the eligibility flags and the new version
+async fn write_ballot(
+    client: &Client,
+    user: &db::User,
+    songs: &[Song],
+    expected_version: Option<i32>,
+) -> Result<(Vec<EligibilityFlag>, i32), AppError> {
+    let db_pool = db::pool()?;
+
+    let (poll, mut rules, overrides) = open_poll_rules(db_pool).await?;
+    let songs = &with_known_release_dates(client, db_pool, poll.as_ref(), songs).await?;
+
+    // Drafts can be saved part way through, the full rules apply once the ballot is submitted
+    let submitted = db::get_ballot_status(db_pool, &user.id)
+        .await?
+        .is_some_and(|ballot| ballot.status == "submitted");
+    if !submitted {
+        rules.min_songs = 0;
+    }
+
+    let check = ballot_validation::validate_ballot(
+        songs,
+        &rules,
+        poll.as_ref().map(|poll| (poll, &overrides)),
+    );
+    if !check.errors.is_empty() {
+        return Err(ballot_breaks_rules(check.errors));
+    }
+
+    // validate_ballot already reports missing ranks, this hands the database only ranked songs
+    let ranked: Vec<(&Song, i32)> = songs
+        .iter()
+        .map(|song| {
+            song.rank.map(|rank| (song, rank)).ok_or_else(|| {
+                AppError::Validation(format!("{} - {} has no rank", song.name, song.artist))
+            })
+        })
+        .collect::<Result<_, _>>()?;
+
+    match db::replace_ballot(db_pool, &user.id, &ranked, expected_version).await? {
+        db::BallotWrite::Saved { version } => Ok((check.flagged, version)),
+        db::BallotWrite::Stale { version } => Err(AppError::StaleVersion(format!(
+            "Your ballot was changed somewhere else and is now at version {}, reload it and try again",
+            version
+        ))),
+    }
+}
+
+async fn save_ballot(
+    cookies: &CookieJar<'_>,
+    client: &Client,
+    if_match: IfMatch,
+    songs: &[Song],
+) -> Result<Versioned<Json<SaveSongsResponse>>, AppError> {
+    let session = SessionUser::require(cookies)?;
+    let db_pool = db::pool()?;
+
+    let user = db::get_or_insert_user(db_pool, &session).await?;
+
+    let (flagged, version) = write_ballot(client, &user, songs, if_match.0).await?;
+
+    Ok(Versioned::new(Json(SaveSongsResponse { flagged }), version))
+}
+
 #[post("/songs", format = "json", data = "<songs>")]
 pub async fn save_songs(
     cookies: &CookieJar<'_>,
+    client: &State<Client>,
+    if_match: IfMatch,
     songs: Json<Vec<Song>>,
-) -> Result<(), (Status, Json<ErrorResponse>)> {
-    let db_pool = DB_POOL.get().unwrap();
-    let user_name_opt = cookies
-        .get_private("user")
-        .map(|cookie| cookie.value().to_string());
-
-    if user_name_opt.is_none() {
-        return Err((
-            Status::InternalServerError,
-            Json(ErrorResponse {
-                error: "No username".to_string(),
-            }),
-        ));
-    }
-
-    let user_name = user_name_opt.unwrap();
-    let user = db::get_or_insert_user(db_pool, &user_name)
-        .await
-        .map_err(|err| {
-            (
-                Default::default(),
-                Json(ErrorResponse {
-                    error: format!("Database error: {}", err),
-                }),
-            )
-        })?;
+) -> Result<Versioned<Json<SaveSongsResponse>>, AppError> {
+    save_ballot(cookies, client, if_match, &songs).await
+}
 
-    db::insert_or_update_songs(db_pool, &user.id, &songs)
-        .await
-        .map_err(|err| {
-            (
-                Status::InternalServerError,
-                Json(ErrorResponse {
-                    error: format!("Failed to insert or update the list of songs: {}", err),
-                }),
-            )
-        })?;
+/// Replaces the whole ballot, deleting any rank that isn't in the new list
+#[put("/ballot", format = "json", data = "<songs>")]
+pub async fn replace_ballot(
+    cookies: &CookieJar<'_>,
+    client: &State<Client>,
+    if_match: IfMatch,
+    songs: Json<Vec<Song>>,
+) -> Result<Versioned<Json<SaveSongsResponse>>, AppError> {
+    save_ballot(cookies, client, if_match, &songs).await
+}
 
-    // add songs
-    Ok(())
+// Loads the user's ballot, applies `edit` to it and saves the result. Without an If-Match the
+// edit is still checked against the version that was loaded, so it can't undo a save that
+// happened in between.
+async fn edit_ballot(
+    cookies: &CookieJar<'_>,
+    client: &Client,
+    if_match: IfMatch,
+    edit: impl FnOnce(&mut Vec<Song>) -> bool,
+    missing: String,
+) -> Result<Versioned<Json<Vec<Song>>>, AppError> {
+    let session = SessionUser::require(cookies)?;
+    let db_pool = db::pool()?;
+    let user = db::get_user(db_pool, &session).await?.ok_or_else(no_ballot)?;
+    let ballot = db::get_ballot_status(db_pool, &user.id)
+        .await?
+        .ok_or_else(no_ballot)?;
+
+    let mut songs = db::get_songs_for_user(db_pool, &user.id).await?;
+    if !edit(&mut songs) {
+        return Err(AppError::NotFound(missing));
+    }
+    songs.sort_by_key(|song| song.rank);
+
+    let (_, version) = write_ballot(client, &user, &songs, Some(if_match.0.unwrap_or(ballot.version))).await?;
+
+    Ok(Versioned::new(Json(songs), version))
 }
 
-#[get("/songs")]
-pub async fn get_songs(
+#[delete("/ballot/entries/<rank>")]
+pub async fn remove_ballot_entry(
     cookies: &CookieJar<'_>,
-) -> Result<Json<Vec<Song>>, (Status, Json<ErrorResponse>)> {
-    let db_pool = DB_POOL.get().unwrap();
-    let user_name_opt = cookies
-        .get_private("user")
-        .map(|cookie| cookie.value().to_string());
+    client: &State<Client>,
+    if_match: IfMatch,
+    rank: i32,
+) -> Result<Versioned<Json<Vec<Song>>>, AppError> {
+    edit_ballot(
+        cookies,
+        client,
+        if_match,
+        |songs| ballot_editing::remove_entry(songs, rank),
+        format!("Nothing at rank {}", rank),
+    )
+    .await
+}
 
-    if user_name_opt.is_none() {
-        return Err((
-            Status::InternalServerError,
-            Json(ErrorResponse {
-                error: "No Username Token".to_string(),
-            }),
-        ));
+#[post("/ballot/entries/<rank>/move?<to>")]
+pub async fn move_ballot_entry(
+    cookies: &CookieJar<'_>,
+    client: &State<Client>,
+    if_match: IfMatch,
+    rank: i32,
+    to: i32,
+) -> Result<Versioned<Json<Vec<Song>>>, AppError> {
+    if !(1..=ballot_validation::MAX_RANK).contains(&to) {
+        return Err(AppError::Validation(format!(
+            "Rank {} is outside 1 to {}",
+            to,
+            ballot_validation::MAX_RANK
+        )));
     }
 
-    let user_name = user_name_opt.unwrap();
-    let songs = db::get_songs_for_user_name(db_pool, &user_name)
-        .await
-        .map_err(|err| {
-            (
-                Default::default(),
-                Json(ErrorResponse {
-                    error: format!("Database error: {}", err),
-                }),
-            )
-        })?;
+    edit_ballot(
+        cookies,
+        client,
+        if_match,
+        |songs| ballot_editing::move_entry(songs, rank, to),
+        format!("Nothing at rank {}", rank),
+    )
+    .await
+}
 
-    rocket::info!("Tracks {:#?}", songs);
+#[get("/songs")]
+pub async fn get_songs(cookies: &CookieJar<'_>) -> Result<Versioned<Json<Vec<Song>>>, AppError> {
+    let session = SessionUser::require(cookies)?;
+    let db_pool = db::pool()?;
 
-    Ok(Json(songs))
+    let Some(user) = db::get_user(db_pool, &session).await? else {
+        return Ok(Versioned::new(Json(Vec::new()), 0));
+    };
+    let version = db::get_ballot_status(db_pool, &user.id)
+        .await?
+        .map_or(0, |ballot| ballot.version);
+    let songs = db::get_songs_for_user(db_pool, &user.id).await?;
+
+    Ok(Versioned::new(Json(songs), version))
+}
+
+#[get("/ballot")]
+pub async fn get_ballot(cookies: &CookieJar<'_>) -> Result<Json<BallotStatus>, AppError> {
+    let session = SessionUser::require(cookies)?;
+    let db_pool = db::pool()?;
+
+    let user = db::get_user(db_pool, &session).await?.ok_or_else(no_ballot)?;
+    let ballot = db::get_ballot_status(db_pool, &user.id)
+        .await?
+        .ok_or_else(no_ballot)?;
+
+    Ok(Json(ballot))
+}
+
+#[post("/ballot/submit")]
+pub async fn submit_ballot(cookies: &CookieJar<'_>) -> Result<Json<BallotStatus>, AppError> {
+    let session = SessionUser::require(cookies)?;
+    let db_pool = db::pool()?;
+    let user = db::get_user(db_pool, &session).await?.ok_or_else(no_ballot)?;
+
+    // The saved ballot has to pass every rule before it counts
+    let songs = db::get_songs_for_user(db_pool, &user.id).await?;
+    let (poll, rules, overrides) = open_poll_rules(db_pool).await?;
+    let check = ballot_validation::validate_ballot(
+        &songs,
+        &rules,
+        poll.as_ref().map(|poll| (poll, &overrides)),
+    );
+    if !check.errors.is_empty() {
+        return Err(ballot_breaks_rules(check.errors));
+    }
+
+    set_ballot_submitted(db_pool, &user, true).await
+}
+
+#[post("/ballot/unsubmit")]
+pub async fn unsubmit_ballot(cookies: &CookieJar<'_>) -> Result<Json<BallotStatus>, AppError> {
+    let session = SessionUser::require(cookies)?;
+    let db_pool = db::pool()?;
+
+    let user = db::get_user(db_pool, &session).await?.ok_or_else(no_ballot)?;
+
+    set_ballot_submitted(db_pool, &user, false).await
+}
+
+async fn set_ballot_submitted(
+    db_pool: &sqlx::PgPool,
+    user: &db::User,
+    submitted: bool,
+) -> Result<Json<BallotStatus>, AppError> {
+    let updated = db::set_ballot_submitted(db_pool, &user.id, submitted).await?;
+    if !updated {
+        return Err(no_ballot());
+    }
+
+    rocket::info!(
+        "{} {} their ballot",
+        user.name,
+        if submitted { "submitted" } else { "withdrew" }
+    );
+
+    db::get_ballot_status(db_pool, &user.id)
+        .await?
+        .map(Json)
+        .ok_or_else(no_ballot)
+}
+
+// Revision history of the named voter's ballot, or the logged in user's own
+async fn ballot_revisions(
+    cookies: &CookieJar<'_>,
+    user: Option<&str>,
+) -> Result<(db::User, Vec<BallotRevision>), AppError> {
+    let session = SessionUser::require(cookies)?;
+    let db_pool = db::pool()?;
+
+    let user = match user {
+        Some(name) => db::get_user_by_name(db_pool, name)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("No voter named {}", name)))?,
+        None => db::get_user(db_pool, &session)
+            .await?
+            .ok_or_else(|| AppError::NotFound("You haven't saved a ballot yet".to_string()))?,
+    };
+    let revisions = db::get_ballot_revisions(db_pool, &user.id).await?;
+
+    Ok((user, revisions))
+}
+
+#[get("/ballot/history?<user>")]
+pub async fn get_ballot_history(
+    cookies: &CookieJar<'_>,
+    user: Option<&str>,
+) -> Result<Json<Vec<BallotRevision>>, AppError> {
+    let (_, revisions) = ballot_revisions(cookies, user).await?;
+    Ok(Json(revisions))
+}
+
+#[get("/ballot/history/diff?<user>&<from>&<to>")]
+pub async fn get_ballot_diff(
+    cookies: &CookieJar<'_>,
+    user: Option<&str>,
+    from: Option<i32>,
+    to: Option<i32>,
+) -> Result<Json<BallotDiff>, AppError> {
+    let (user, revisions) = ballot_revisions(cookies, user).await?;
+
+    // Defaults to the latest revision against the one before it
+    let to = to.or(revisions.first().map(|revision| revision.version));
+    let from = from.or(to.map(|to| to - 1));
+    let find = |version: Option<i32>| {
+        version
+            .and_then(|version| revisions.iter().find(|revision| revision.version == version))
+            .ok_or_else(|| {
+                AppError::NotFound(match version {
+                    Some(version) => format!("{} has no ballot revision {}", user.name, version),
+                    None => format!("{} has no ballot revisions", user.name),
+                })
+            })
+    };
+    let to = find(to)?;
+    let from = find(from)?;
+
+    Ok(Json(BallotDiff {
+        user_name: user.label.clone(),
+        from_version: from.version,
+        from_created_at: from.created_at,
+        to_version: to.version,
+        to_created_at: to.created_at,
+        changes: ballot_history::diff_revisions(&from.songs, &to.songs),
+    }))
 }
 
+#[get("/ballots/submissions")]
+pub async fn get_ballot_submissions() -> Result<Json<BallotSubmissions>, AppError> {
+    let db_pool = db::pool()?;
+
+    let submissions = db::get_ballot_submissions(db_pool).await?;
+
+    Ok(Json(submissions))
+}
+
+/// Searches Spotify, with songs already in our catalogue that match the track text ahead of
+/// the Spotify results on the first page. Catalogue hits aren't counted in the paging fields.
 #[get("/search-songs?<query..>")]
 pub async fn search_songs(
     cookies: &CookieJar<'_>,
     query: Option<SearchSongsQuery>,
     client: &State<Client>,
-) -> Result<Json<Vec<Song>>, (Status, Json<ErrorResponse>)> {
-    search_spotify_songs(cookies, query, client).await
+) -> Result<Json<SearchPage>, AppError> {
+    // Searches use the app's Spotify token, so only signed in voters (guests included) get one
+    SessionUser::require(cookies)?;
+
+    let track = query
+        .as_ref()
+        .and_then(|query| query.track.as_deref())
+        .map(str::trim)
+        .filter(|track| !track.is_empty() && parse_track_link(track).is_none())
+        .map(String::from);
+    let rank = query.as_ref().and_then(|query| query.rank);
+
+    // Validates the query, so a bad one is turned away before the catalogue is searched
+    let Json(mut page) = search_spotify_songs(query, client).await?;
+    let Some(track) = track.filter(|_| page.offset == 0) else {
+        return Ok(Json(page));
+    };
+
+    let db_pool = db::pool()?;
+    let mut songs = db::search_catalogue(db_pool, &track, CATALOGUE_SEARCH_LIMIT).await?;
+    let local_uris: HashSet<String> = songs.iter().map(|song| song.uri.clone()).collect();
+    for song in &mut songs {
+        song.rank = rank;
+    }
+    songs.extend(
+        page.songs
+            .into_iter()
+            .filter(|song| !local_uris.contains(&song.uri)),
+    );
+    page.songs = songs;
+
+    Ok(Json(page))
 }
 
 #[get("/generate_playlist")]
 pub async fn generate_playlist(
     cookies: &CookieJar<'_>,
     client: &State<Client>,
-) -> Result<(), (Status, Json<ErrorResponse>)> {
-    let db_pool = DB_POOL.get().unwrap();
-
-    let ranked_songs = db::get_song_rankings(db_pool).await.map_err(|err| {
-        (
-            Status::InternalServerError,
-            Json(ErrorResponse {
-                error: format!("Failed to get the top songs: {}", err),
-            }),
-        )
-    })?;
+) -> Result<(), AppError> {
+    let db_pool = db::pool()?;
 
-    let playlist_id = create_playlist(cookies, client).await.map_err(|err| {
-        (
-            Status::InternalServerError,
-            Json(ErrorResponse {
-                error: format!("Failed to create Playlist via Spotify API: {:?}", err.1),
-            }),
-        )
-    })?;
+    let ranked_songs = db::get_song_rankings(db_pool).await?;
+
+    let playlist_id = create_playlist(cookies, client, "Hottest100", "Hottest100").await?;
 
     // pass the playlist id into the external function with the songs to make the playlist
-    add_songs_to_playlist(playlist_id, ranked_songs, &cookies, &client).await
+    add_songs_to_playlist(playlist_id, ranked_songs, cookies, client).await
 }
 
 #[get("/music-taste")]
-pub async fn get_music_taste() -> Result<Json<Vec<MusicTasteOverview>>, (Status, Json<ErrorResponse>)> {
-    let db_pool = DB_POOL.get().unwrap();
+pub async fn get_music_taste() -> Result<Json<Vec<MusicTasteOverview>>, AppError> {
+    let db_pool = db::pool()?;
 
-    let overview = db::get_music_taste_overview(db_pool)
-        .await
-        .map_err(|err| {
-            (
-                Status::InternalServerError,
-                Json(ErrorResponse {
-                    error: format!("Failed to get music taste overview: {}", err),
-                }),
+    let overview = db::get_music_taste_overview(db_pool).await?;
+
+    Ok(Json(overview))
+}
+
+#[get("/music-taste/clusters?<min_score>")]
+pub async fn get_taste_clusters(
+    min_score: Option<f64>,
+) -> Result<Json<Vec<TasteCluster>>, AppError> {
+    let db_pool = db::pool()?;
+
+    let pairs = db::get_taste_pairs(db_pool).await?;
+    let votes = db::get_all_rankings(db_pool).await?;
+
+    let clusters = analytics::cluster_voters(
+        &pairs,
+        &votes,
+        min_score.unwrap_or(DEFAULT_CLUSTER_MIN_SCORE),
+    );
+
+    Ok(Json(clusters))
+}
+
+#[get("/music-taste/graph?<format>&<min_score>&<top_k>")]
+pub async fn get_taste_graph(
+    format: Option<GraphFormat>,
+    min_score: Option<f64>,
+    top_k: Option<usize>,
+) -> Result<(ContentType, String), AppError> {
+    let db_pool = db::pool()?;
+
+    let voters = db::get_voters(db_pool).await?;
+    let pairs = db::get_taste_pairs(db_pool).await?;
+
+    let graph = taste_graph::build_taste_graph(
+        &voters,
+        &pairs,
+        min_score.unwrap_or(DEFAULT_GRAPH_MIN_SCORE),
+        top_k.unwrap_or(DEFAULT_GRAPH_TOP_K),
+    );
+
+    match format.unwrap_or_default() {
+        GraphFormat::Json => {
+            let body = serde_json::to_string(&graph).map_err(|err| {
+                AppError::Internal(format!("Failed to serialise taste graph: {}", err))
+            })?;
+            Ok((ContentType::JSON, body))
+        }
+        GraphFormat::Graphml => Ok((
+            ContentType::new("application", "graphml+xml"),
+            taste_graph::to_graphml(&graph),
+        )),
+        GraphFormat::Dot => Ok((
+            ContentType::new("text", "vnd.graphviz"),
+            taste_graph::to_dot(&graph),
+        )),
+    }
+}
+
+#[get("/recommendations?<limit>")]
+pub async fn get_recommendations(
+    cookies: &CookieJar<'_>,
+    limit: Option<usize>,
+) -> Result<Json<Vec<Recommendation>>, AppError> {
+    let session = SessionUser::require(cookies)?;
+    let db_pool = db::pool()?;
+
+    let user = db::get_user(db_pool, &session).await?.ok_or_else(|| {
+        AppError::NotFound("Save some songs to get recommendations".to_string())
+    })?;
+
+    let already_ranked = db::get_ranked_song_ids(db_pool, &user.id).await?;
+    let matches = db::get_music_taste_user(db_pool, &user.id, None).await?;
+    let votes = db::get_all_rankings(db_pool).await?;
+
+    Ok(Json(recommendations::recommend_songs(
+        user.id,
+        &already_ranked,
+        &matches,
+        &votes,
+        limit.unwrap_or(DEFAULT_RECOMMENDATION_LIMIT),
+    )))
+}
+
+#[get("/voters")]
+pub async fn get_voters() -> Result<Json<Vec<String>>, AppError> {
+    let db_pool = db::pool()?;
+
+    let voters = db::get_voters(db_pool).await?;
+
+    Ok(Json(voters.into_iter().map(|voter| voter.name).collect()))
+}
+
+// Long enough for a name, short enough to fit the taste and results layouts
+const MAX_NICKNAME_LENGTH: usize = 40;
+
+fn profile_missing() -> AppError {
+    AppError::NotFound("Log in again to load your profile".to_string())
+}
+
+#[get("/profile")]
+pub async fn get_profile(cookies: &CookieJar<'_>) -> Result<Json<UserProfile>, AppError> {
+    let session = SessionUser::require(cookies)?;
+    let db_pool = db::pool()?;
+    let profile = db::get_user_profile(db_pool, &session)
+        .await?
+        .ok_or_else(profile_missing)?;
+
+    Ok(Json(profile))
+}
+
+#[put("/profile/nickname", data = "<request>")]
+pub async fn set_nickname(
+    cookies: &CookieJar<'_>,
+    request: Json<NicknameRequest>,
+) -> Result<Json<UserProfile>, AppError> {
+    let session = SessionUser::require(cookies)?;
+
+    let nickname = request
+        .nickname
+        .as_deref()
+        .map(str::trim)
+        .filter(|nickname| !nickname.is_empty());
+    if let Some(nickname) = nickname {
+        if nickname.chars().count() > MAX_NICKNAME_LENGTH {
+            return Err(AppError::Validation(format!(
+                "Nicknames can be at most {} characters",
+                MAX_NICKNAME_LENGTH
+            )));
+        }
+    }
+
+    let db_pool = db::pool()?;
+    let user = db::get_user(db_pool, &session)
+        .await?
+        .ok_or_else(profile_missing)?;
+
+    match db::set_nickname(db_pool, user.id, nickname).await? {
+        db::NicknameUpdate::Saved(profile) => {
+            rocket::info!("{} is now known as {}", user.name, profile.label);
+            Ok(Json(profile))
+        }
+        db::NicknameUpdate::Taken => Err(AppError::Conflict(format!(
+            "The nickname {} is already taken",
+            nickname.unwrap_or_default()
+        ))),
+    }
+}
+
+
+// Short enough to type on a phone, long enough not to guess in a few tries
+fn valid_pin(pin: &str) -> bool {
+    (4..=8).contains(&pin.len()) && pin.chars().all(|c| c.is_ascii_digit())
+}
+
+#[post("/guest/join", format = "json", data = "<request>")]
+pub async fn join_as_guest(
+    cookies: &CookieJar<'_>,
+    request: Json<GuestJoinRequest>,
+) -> Result<Json<GuestLogin>, AppError> {
+    let name = request.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NICKNAME_LENGTH {
+        return Err(AppError::Validation(format!(
+            "Names need between 1 and {} characters",
+            MAX_NICKNAME_LENGTH
+        )));
+    }
+    if !valid_pin(&request.pin) {
+        return Err(AppError::Validation("PINs are 4 to 8 digits".to_string()));
+    }
+
+    let db_pool = db::pool()?;
+    let guest = db::create_guest(db_pool, request.code.trim(), name, &request.pin)
+        .await?
+        .ok_or_else(|| {
+            AppError::Forbidden("That invite code is invalid, expired or used up".to_string())
+        })?;
+
+    rocket::info!("Guest {} joined as {}", guest.guest_id, guest.profile.label);
+    SessionUser::guest(&guest.guest_id).save(cookies);
+    Ok(Json(guest))
+}
+
+#[post("/guest/login", format = "json", data = "<request>")]
+pub async fn guest_login(
+    cookies: &CookieJar<'_>,
+    request: Json<GuestLoginRequest>,
+) -> Result<Json<GuestLogin>, AppError> {
+    let db_pool = db::pool()?;
+
+    let guest_id = request.guest_id.trim().to_lowercase();
+    let profile = db::check_guest_pin(db_pool, &guest_id, &request.pin)
+        .await?
+        .ok_or_else(|| {
+            AppError::Unauthenticated(
+                "Unknown guest ID or wrong PIN. Too many wrong PINs lock you out for a while."
+                    .to_string(),
             )
         })?;
 
-    Ok(Json(overview))
+    SessionUser::guest(&guest_id).save(cookies);
+    Ok(Json(GuestLogin { guest_id, profile }))
+}
+
+// Limits results from the shared catalogue to what fits the search panel
+const CATALOGUE_SEARCH_LIMIT: i64 = 20;
+
+#[get("/catalogue?<track>&<rank>")]
+pub async fn search_catalogue(
+    cookies: &CookieJar<'_>,
+    track: String,
+    rank: Option<i32>,
+) -> Result<Json<Vec<Song>>, AppError> {
+    SessionUser::require(cookies)?;
+
+    let track = track.trim();
+    if track.is_empty() {
+        return Err(AppError::Validation("Search needs a track".to_string()));
+    }
+
+    let db_pool = db::pool()?;
+    let mut songs = db::search_catalogue(db_pool, track, CATALOGUE_SEARCH_LIMIT).await?;
+    for song in &mut songs {
+        song.rank = rank;
+    }
+
+    Ok(Json(songs))
+}
+
+// Invites are for one poll season, and this keeps the expiry date arithmetic in range
+const MAX_INVITE_DAYS: i64 = 365;
+
+#[post("/admin/guest-invites", format = "json", data = "<request>")]
+pub async fn create_guest_invite(
+    cookies: &CookieJar<'_>,
+    request: Json<GuestInviteRequest>,
+) -> Result<Json<GuestInvite>, AppError> {
+    let admin = require_admin(cookies)?;
+
+    if request.max_uses.is_some_and(|max_uses| max_uses < 1) {
+        return Err(AppError::Validation("max_uses must be at least 1".to_string()));
+    }
+    if request
+        .expires_in_days
+        .is_some_and(|days| !(1..=MAX_INVITE_DAYS).contains(&days))
+    {
+        return Err(AppError::Validation(format!(
+            "expires_in_days must be from 1 to {}",
+            MAX_INVITE_DAYS
+        )));
+    }
+    let expires_at = request
+        .expires_in_days
+        .map(|days| Utc::now() + Duration::days(days));
+
+    let db_pool = db::pool()?;
+    let admin = db::get_or_insert_user(db_pool, &admin).await?;
+    let invite = db::create_guest_invite(db_pool, &admin.id, request.max_uses, expires_at).await?;
+
+    Ok(Json(invite))
+}
+
+#[get("/admin/guest-invites")]
+pub async fn get_guest_invites(cookies: &CookieJar<'_>) -> Result<Json<Vec<GuestInvite>>, AppError> {
+    require_admin(cookies)?;
+    let db_pool = db::pool()?;
+
+    let invites = db::get_guest_invites(db_pool).await?;
+
+    Ok(Json(invites))
+}
+
+#[post("/admin/guest-invites/<invite_id>/revoke")]
+pub async fn revoke_guest_invite(cookies: &CookieJar<'_>, invite_id: i32) -> Result<(), AppError> {
+    require_admin(cookies)?;
+    let db_pool = db::pool()?;
+
+    let revoked = db::revoke_guest_invite(db_pool, &invite_id).await?;
+    if !revoked {
+        return Err(AppError::NotFound(format!("No active invite {}", invite_id)));
+    }
+
+    Ok(())
+}
+
+#[get("/generate_blend?<user>")]
+pub async fn generate_blend(
+    cookies: &CookieJar<'_>,
+    client: &State<Client>,
+    user: String,
+) -> Result<(), AppError> {
+    let session = SessionUser::require(cookies)?;
+    let db_pool = db::pool()?;
+
+    let (my_label, my_songs) = match db::get_user(db_pool, &session).await? {
+        Some(me) => (me.label, db::get_songs_for_user(db_pool, &me.id).await?),
+        None => (session.name(), Vec::new()),
+    };
+    let (their_label, their_songs) = match db::get_user_by_name(db_pool, &user).await? {
+        Some(them) => (them.label, db::get_songs_for_user(db_pool, &them.id).await?),
+        None => (user.clone(), Vec::new()),
+    };
+
+    if their_songs.is_empty() {
+        return Err(AppError::NotFound(format!("{} has no songs to blend", user)));
+    }
+
+    let blended_songs = blend::blend_ballots(&my_songs, &their_songs);
+
+    let playlist_id = create_playlist(
+        cookies,
+        client,
+        "Hottest100 Blend",
+        &format!("Hottest100 blend of {} and {}", my_label, their_label),
+    )
+    .await?;
+
+    add_songs_to_playlist(playlist_id, blended_songs, cookies, client).await
+}
+
+// Admins are the user names (e.g. spotify:user:abc) listed in ADMIN_USERS, comma separated
+fn require_admin(cookies: &CookieJar<'_>) -> Result<SessionUser, AppError> {
+    let session = SessionUser::require(cookies)?;
+    let user_name = session.name();
+
+    // Entries may still carry the quotes user names used to be stored with
+    let admins = env::var("ADMIN_USERS").unwrap_or_default();
+    if admins
+        .split(',')
+        .any(|admin| admin.trim().trim_matches('"') == user_name)
+    {
+        Ok(session)
+    } else {
+        Err(AppError::Forbidden("Admin access required".to_string()))
+    }
+}
+
+#[post("/admin/polls?<name>")]
+pub async fn open_poll(cookies: &CookieJar<'_>, name: String) -> Result<Json<Poll>, AppError> {
+    require_admin(cookies)?;
+    let db_pool = db::pool()?;
+
+    if let Some(poll) = db::get_open_poll(db_pool).await? {
+        return Err(AppError::Conflict(format!("{} is still open", poll.name)));
+    }
+
+    let poll = db::create_poll(db_pool, &name).await?;
+
+    Ok(Json(poll))
+}
+
+#[post("/admin/polls/close")]
+pub async fn close_poll(cookies: &CookieJar<'_>) -> Result<(), AppError> {
+    require_admin(cookies)?;
+    let db_pool = db::pool()?;
+
+    let poll = db::get_open_poll(db_pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("There is no open poll to close".to_string()))?;
+
+    let ranked_songs = db::get_song_rankings(db_pool).await?;
+
+    db::close_poll(db_pool, &poll.id, &ranked_songs).await?;
+
+    Ok(())
+}
+
+#[get("/wrapped")]
+pub async fn get_wrapped(cookies: &CookieJar<'_>) -> Result<Json<WrappedSummary>, AppError> {
+    let session = SessionUser::require(cookies)?;
+    let db_pool = db::pool()?;
+
+    let user = db::get_user(db_pool, &session)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Save some songs to see your summary".to_string()))?;
+
+    let poll = db::get_latest_closed_poll(db_pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("No poll has closed yet".to_string()))?;
+
+    let picks = db::get_poll_picks_for_user(db_pool, &poll.id, &user.id).await?;
+    if picks.is_empty() {
+        return Err(AppError::NotFound(format!(
+            "You didn't submit a ballot in {}",
+            poll.name
+        )));
+    }
+    let countdown = db::get_poll_countdown(db_pool, &poll.id).await?;
+    let vote_counts = db::get_poll_vote_counts(db_pool, &poll.id).await?;
+    let matches = db::get_music_taste_user(db_pool, &user.id, Some(poll.id)).await?;
+
+    let previous = match db::get_previous_closed_poll(db_pool, &poll).await? {
+        Some(poll) => {
+            let previous_picks =
+                db::get_poll_picks_for_user(db_pool, &poll.id, &user.id).await?;
+            Some((poll, previous_picks)).filter(|(_, picks)| !picks.is_empty())
+        }
+        None => None,
+    };
+
+    Ok(Json(wrapped::build_wrapped_summary(
+        &user.label,
+        &poll,
+        &picks,
+        countdown.len(),
+        &vote_counts,
+        &matches,
+        previous,
+    )))
+}
+
+#[post("/predictions", format = "json", data = "<songs>")]
+pub async fn save_predictions(
+    cookies: &CookieJar<'_>,
+    songs: Json<Vec<Song>>,
+) -> Result<(), AppError> {
+    let session = SessionUser::require(cookies)?;
+
+    if let Some(song) = songs
+        .iter()
+        .find(|song| !matches!(song.rank, Some(1..=100)))
+    {
+        return Err(AppError::Validation(format!(
+            "{} needs a predicted position from 1 to 100",
+            song.name
+        )));
+    }
+
+    let mut seen_positions: HashMap<i32, usize> = HashMap::new();
+    let mut seen_uris: HashMap<&str, usize> = HashMap::new();
+    for (row, song) in songs.iter().enumerate() {
+        let position = song.rank.unwrap_or_default();
+        if let Some(first) = seen_positions.insert(position, row) {
+            return Err(AppError::Validation(format!(
+                "Rows {} and {} both predict position {}",
+                first, row, position
+            )));
+        }
+        if let Some(first) = seen_uris.insert(song.uri.as_str(), row) {
+            return Err(AppError::Validation(format!(
+                "Rows {} and {} both predict {}",
+                first, row, song.name
+            )));
+        }
+    }
+
+    let db_pool = db::pool()?;
+    let poll = db::get_open_poll(db_pool)
+        .await?
+        .ok_or_else(|| AppError::Conflict("There is no open poll to predict".to_string()))?;
+
+    let user = db::get_or_insert_user(db_pool, &session).await?;
+
+    db::replace_predictions(db_pool, &poll.id, &user.id, &songs).await?;
+
+    Ok(())
+}
+
+#[get("/predictions")]
+pub async fn get_predictions(cookies: &CookieJar<'_>) -> Result<Json<Vec<Song>>, AppError> {
+    let session = SessionUser::require(cookies)?;
+    let db_pool = db::pool()?;
+
+    let Some(poll) = db::get_open_poll(db_pool).await? else {
+        return Ok(Json(Vec::new()));
+    };
+    let Some(user) = db::get_user(db_pool, &session).await? else {
+        return Ok(Json(Vec::new()));
+    };
+
+    let songs = db::get_predictions_for_user(db_pool, &poll.id, &user.id).await?;
+
+    Ok(Json(songs))
+}
+
+async fn import_official_results(
+    poll_id: i32,
+    results: &[OfficialResultEntry],
+) -> Result<Json<OfficialResultsImport>, AppError> {
+    let db_pool = db::pool()?;
+
+    if db::get_poll(db_pool, &poll_id).await?.is_none() {
+        return Err(AppError::NotFound(format!("No poll with id {}", poll_id)));
+    }
+
+    let mut matched: Vec<(i32, i32)> = Vec::new();
+    let mut unmatched: Vec<String> = Vec::new();
+    for entry in results {
+        let song_id = db::find_song_id(
+            db_pool,
+            entry.uri.as_deref(),
+            entry.name.as_deref(),
+            entry.artist.as_deref(),
+        )
+        .await?;
+
+        match song_id {
+            Some(song_id) if entry.position >= 1 => matched.push((song_id, entry.position)),
+            _ => unmatched.push(format!(
+                "#{} {}",
+                entry.position,
+                entry.uri.clone().unwrap_or_else(|| format!(
+                    "{} - {}",
+                    entry.name.as_deref().unwrap_or_default(),
+                    entry.artist.as_deref().unwrap_or_default()
+                ))
+            )),
+        }
+    }
+
+    db::replace_official_results(db_pool, &poll_id, &matched).await?;
+
+    Ok(Json(OfficialResultsImport {
+        matched: matched.len(),
+        unmatched,
+    }))
+}
+
+#[post("/admin/polls/<poll_id>/official-results", format = "json", data = "<results>")]
+pub async fn load_official_results(
+    cookies: &CookieJar<'_>,
+    poll_id: i32,
+    results: Json<Vec<OfficialResultEntry>>,
+) -> Result<Json<OfficialResultsImport>, AppError> {
+    require_admin(cookies)?;
+    import_official_results(poll_id, &results).await
+}
+
+#[post("/admin/polls/<poll_id>/official-results", format = "text/csv", data = "<results>", rank = 2)]
+pub async fn load_official_results_csv(
+    cookies: &CookieJar<'_>,
+    poll_id: i32,
+    results: String,
+) -> Result<Json<OfficialResultsImport>, AppError> {
+    require_admin(cookies)?;
+
+    let results = countdown_comparison::parse_official_results_csv(&results).map_err(|err| {
+        AppError::Validation(format!("Failed to parse official results CSV: {}", err))
+    })?;
+
+    import_official_results(poll_id, &results).await
+}
+
+fn no_official_results() -> AppError {
+    AppError::NotFound("No official results have been loaded yet".to_string())
+}
+
+#[get("/official-results/compare?<poll_id>")]
+pub async fn compare_official_results(
+    poll_id: Option<i32>,
+) -> Result<Json<CountdownComparison>, AppError> {
+    let db_pool = db::pool()?;
+
+    let poll = match poll_id {
+        Some(poll_id) => db::get_poll(db_pool, &poll_id).await?,
+        None => db::get_latest_poll_with_official_results(db_pool).await?,
+    }
+    .ok_or_else(no_official_results)?;
+
+    // A closed poll compares its archived countdown, an open one compares the live rankings
+    let group = if poll.closed_at.is_some() {
+        db::get_poll_countdown(db_pool, &poll.id).await?
+    } else {
+        let ranked_songs = db::get_song_rankings(db_pool).await?;
+        db::get_countdown_songs(db_pool, &ranked_songs).await?
+    };
+
+    let official = db::get_official_countdown(db_pool, &poll.id).await?;
+
+    Ok(Json(countdown_comparison::compare_countdowns(
+        &poll.name, &group, &official,
+    )))
+}
+
+// Far above any sensible scoring, and keeps the point totals from overflowing
+const MAX_RULE_POINTS: i64 = 1000;
+
+#[get("/predictions/leaderboard?<poll_id>&<rules..>")]
+pub async fn get_prediction_leaderboard(
+    poll_id: Option<i32>,
+    rules: ScoringRules,
+) -> Result<Json<Vec<LeaderboardEntry>>, AppError> {
+    let points = [rules.exact, rules.top_10, rules.in_list, rules.distance];
+    if points.iter().any(|points| !(0..=MAX_RULE_POINTS).contains(points)) {
+        return Err(AppError::Validation(format!(
+            "Scoring rules must each be from 0 to {} points",
+            MAX_RULE_POINTS
+        )));
+    }
+
+    let db_pool = db::pool()?;
+
+    let poll = match poll_id {
+        Some(poll_id) => db::get_poll(db_pool, &poll_id).await?,
+        None => db::get_latest_poll_with_official_results(db_pool).await?,
+    }
+    .ok_or_else(no_official_results)?;
+
+    let predictions = db::get_predictions(db_pool, &poll.id).await?;
+    let official = db::get_official_results(db_pool, &poll.id).await?;
+
+    Ok(Json(predictions::score_predictions(
+        &predictions,
+        &official,
+        &rules,
+    )))
+}
+
+#[put("/admin/polls/<poll_id>/eligibility", format = "json", data = "<window>")]
+pub async fn set_poll_eligibility(
+    cookies: &CookieJar<'_>,
+    poll_id: i32,
+    window: Json<EligibilityWindow>,
+) -> Result<Json<Poll>, AppError> {
+    require_admin(cookies)?;
+
+    if let (Some(from), Some(to)) = (window.eligible_from, window.eligible_to) {
+        if from > to {
+            return Err(AppError::Validation(format!(
+                "eligible_from {} is after eligible_to {}",
+                from, to
+            )));
+        }
+    }
+
+    let db_pool = db::pool()?;
+
+    db::set_poll_eligibility(db_pool, &poll_id, window.eligible_from, window.eligible_to)
+        .await?
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound(format!("No poll with id {}", poll_id)))
+}
+
+#[get("/ballot-rules")]
+pub async fn get_ballot_rules() -> Result<Json<BallotRules>, AppError> {
+    let db_pool = db::pool()?;
+
+    let (_, rules, _) = open_poll_rules(db_pool).await?;
+
+    Ok(Json(rules))
+}
+
+#[put("/admin/polls/<poll_id>/ballot-rules", format = "json", data = "<rules>")]
+pub async fn set_ballot_rules(
+    cookies: &CookieJar<'_>,
+    poll_id: i32,
+    rules: Json<BallotRules>,
+) -> Result<Json<BallotRules>, AppError> {
+    require_admin(cookies)?;
+
+    if rules.min_songs > rules.max_songs || rules.max_songs > ballot_validation::MAX_RANK as usize {
+        return Err(AppError::Validation(format!(
+            "Ballot length must satisfy min_songs <= max_songs <= {}",
+            ballot_validation::MAX_RANK
+        )));
+    }
+    if rules.max_per_artist == Some(0) {
+        return Err(AppError::Validation("max_per_artist must be at least 1".to_string()));
+    }
+
+    let db_pool = db::pool()?;
+    let updated = db::set_ballot_rules(db_pool, &poll_id, &rules).await?;
+    if !updated {
+        return Err(AppError::NotFound(format!("No poll with id {}", poll_id)));
+    }
+
+    Ok(rules)
+}
+
+#[get("/admin/polls/<poll_id>/eligibility-overrides")]
+pub async fn get_eligibility_overrides(
+    cookies: &CookieJar<'_>,
+    poll_id: i32,
+) -> Result<Json<Vec<String>>, AppError> {
+    require_admin(cookies)?;
+    let db_pool = db::pool()?;
+
+    let overrides = db::get_eligibility_overrides(db_pool, &poll_id).await?;
+
+    Ok(Json(overrides))
+}
+
+#[post("/admin/polls/<poll_id>/eligibility-overrides", format = "json", data = "<song>")]
+pub async fn add_eligibility_override(
+    cookies: &CookieJar<'_>,
+    poll_id: i32,
+    song: Json<EligibilityOverride>,
+) -> Result<(), AppError> {
+    require_admin(cookies)?;
+    let db_pool = db::pool()?;
+
+    db::add_eligibility_override(db_pool, &poll_id, &song.uri).await?;
+
+    Ok(())
+}
+
+#[delete("/admin/polls/<poll_id>/eligibility-overrides", format = "json", data = "<song>")]
+pub async fn remove_eligibility_override(
+    cookies: &CookieJar<'_>,
+    poll_id: i32,
+    song: Json<EligibilityOverride>,
+) -> Result<(), AppError> {
+    require_admin(cookies)?;
+    let db_pool = db::pool()?;
+
+    db::remove_eligibility_override(db_pool, &poll_id, &song.uri).await?;
+
+    Ok(())
+}
+
+#[post("/admin/songs/backfill-metadata")]
+pub async fn backfill_song_metadata(
+    cookies: &CookieJar<'_>,
+    client: &State<Client>,
+) -> Result<Json<MetadataBackfill>, AppError> {
+    require_admin(cookies)?;
+    let db_pool = db::pool()?;
+
+    let access_token = app_access_token(client).await?;
+
+    let uris = db::get_songs_missing_metadata(db_pool).await?;
+    let tracks = get_tracks(client, &access_token, &uris).await?;
+
+    for track in &tracks {
+        db::update_song_metadata(db_pool, track).await?;
+    }
+
+    let found: HashSet<&str> = tracks.iter().map(|track| track.uri.as_str()).collect();
+    let not_found: Vec<String> = uris
+        .iter()
+        .filter(|uri| !found.contains(uri.as_str()))
+        .cloned()
+        .collect();
+
+    rocket::info!(
+        "Backfilled metadata for {} songs, {} not found",
+        tracks.len(),
+        not_found.len()
+    );
+
+    Ok(Json(MetadataBackfill {
+        updated: tracks.len(),
+        not_found,
+    }))
+}
+
+#[post("/admin/songs/canonical-scan")]
+pub async fn scan_canonical_songs(cookies: &CookieJar<'_>) -> Result<Json<CanonicalScan>, AppError> {
+    require_admin(cookies)?;
+    let db_pool = db::pool()?;
+
+    let scan = db::scan_canonical_songs(db_pool).await?;
+
+    rocket::info!(
+        "Canonical scan linked {} songs by ISRC and suggested {} merges",
+        scan.linked,
+        scan.suggested
+    );
+
+    Ok(Json(scan))
+}
+
+#[get("/admin/songs/merge-suggestions?<status>")]
+pub async fn get_merge_suggestions(
+    cookies: &CookieJar<'_>,
+    status: Option<&str>,
+) -> Result<Json<Vec<MergeSuggestion>>, AppError> {
+    require_admin(cookies)?;
+
+    let status = status.unwrap_or("pending");
+    if !["pending", "confirmed", "rejected"].contains(&status) {
+        return Err(AppError::Validation(format!("Unknown suggestion status {}", status)));
+    }
+
+    let db_pool = db::pool()?;
+    let suggestions = db::get_merge_suggestions(db_pool, status).await?;
+
+    Ok(Json(suggestions))
+}
+
+async fn review_merge_suggestion(
+    cookies: &CookieJar<'_>,
+    suggestion_id: i32,
+    confirm: bool,
+) -> Result<(), AppError> {
+    let admin = require_admin(cookies)?.name();
+    let db_pool = db::pool()?;
+
+    let reviewed = db::review_merge_suggestion(db_pool, &suggestion_id, confirm, &admin).await?;
+    if !reviewed {
+        return Err(AppError::NotFound(format!(
+            "No pending merge suggestion {}",
+            suggestion_id
+        )));
+    }
+
+    rocket::info!(
+        "{} {} merge suggestion {}",
+        admin,
+        if confirm { "confirmed" } else { "rejected" },
+        suggestion_id
+    );
+
+    Ok(())
+}
+
+#[post("/admin/songs/merge-suggestions/<id>/confirm")]
+pub async fn confirm_merge_suggestion(cookies: &CookieJar<'_>, id: i32) -> Result<(), AppError> {
+    review_merge_suggestion(cookies, id, true).await
+}
+
+#[post("/admin/songs/merge-suggestions/<id>/reject")]
+pub async fn reject_merge_suggestion(cookies: &CookieJar<'_>, id: i32) -> Result<(), AppError> {
+    review_merge_suggestion(cookies, id, false).await
+}
+
+#[post("/admin/songs/merge", format = "json", data = "<merge>")]
+pub async fn merge_songs(
+    cookies: &CookieJar<'_>,
+    merge: Json<SongMergeRequest>,
+) -> Result<Json<SongMerge>, AppError> {
+    let admin = require_admin(cookies)?.name();
+
+    if merge.from_song_id == merge.into_song_id {
+        return Err(AppError::Validation("Can't merge a song into itself".to_string()));
+    }
+
+    let db_pool = db::pool()?;
+    let merged = db::merge_songs(db_pool, &merge.from_song_id, &merge.into_song_id, &admin)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Both songs must exist to merge them".to_string()))?;
+
+    rocket::info!(
+        "{} merged song {} into {}, moved {} votes and dropped {}",
+        admin,
+        merged.from_song_id,
+        merged.into_song_id,
+        merged.moved_rankings.len(),
+        merged.dropped_rankings.len()
+    );
+
+    Ok(Json(merged))
+}
+
+#[get("/admin/songs/merges")]
+pub async fn get_song_merges(cookies: &CookieJar<'_>) -> Result<Json<Vec<SongMerge>>, AppError> {
+    require_admin(cookies)?;
+    let db_pool = db::pool()?;
+
+    let merges = db::get_song_merges(db_pool).await?;
+
+    Ok(Json(merges))
+}
+
+#[post("/admin/songs/merges/<id>/undo")]
+pub async fn undo_song_merge(
+    cookies: &CookieJar<'_>,
+    id: i32,
+) -> Result<Json<SongMergeUndo>, AppError> {
+    let admin = require_admin(cookies)?.name();
+    let db_pool = db::pool()?;
+
+    let undo = db::undo_song_merge(db_pool, &id, &admin)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("No merge {} to undo", id)))?;
+
+    rocket::info!(
+        "{} undid song merge {}, restored {} votes and skipped {}",
+        admin,
+        id,
+        undo.restored,
+        undo.skipped
+    );
+
+    Ok(Json(undo))
+}
+
+#[post("/admin/songs/<id>/split")]
+pub async fn split_song(cookies: &CookieJar<'_>, id: i32) -> Result<(), AppError> {
+    let admin = require_admin(cookies)?.name();
+    let db_pool = db::pool()?;
+
+    let split = db::split_song(db_pool, &id, &admin).await?;
+    if !split {
+        return Err(AppError::NotFound(format!(
+            "Song {} isn't folded into another song",
+            id
+        )));
+    }
+
+    rocket::info!("{} split song {} out of its canonical song", admin, id);
+
+    Ok(())
+}
+
+#[get("/admin/migrations")]
+pub async fn get_migration_status(
+    cookies: &CookieJar<'_>,
+) -> Result<Json<Vec<MigrationStatus>>, AppError> {
+    require_admin(cookies)?;
+    let db_pool = db::pool()?;
+
+    let statuses = db::get_migration_status(db_pool)
+        .await
+        .map_err(sqlx::Error::from)?;
+
+    Ok(Json(statuses))
 }
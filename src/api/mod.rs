@@ -1,4 +1,20 @@
 pub mod internal_api;
 pub mod external_api;
 mod types;
-pub mod db;
\ No newline at end of file
+pub mod db;
+mod error;
+mod analytics;
+mod taste_graph;
+mod recommendations;
+mod blend;
+mod wrapped;
+mod predictions;
+mod countdown_comparison;
+mod eligibility;
+mod canonical;
+mod ballot_validation;
+mod ballot_history;
+mod ballot_editing;
+mod session;
+mod search_cache;
+mod search_query;
\ No newline at end of file
@@ -0,0 +1,121 @@
+use crate::api::db::{OfficialResult, PlacedSong};
+use crate::api::types::{LeaderboardEntry, ScoringRules};
+use std::collections::{BTreeMap, HashMap};
+
+/// Scores every prediction ballot against the official countdown. Each predicted song earns,
+/// cumulatively:
+///
+/// - `in_list` if it made the official countdown at all,
+/// - `top_10` if it was predicted in the top 10 and finished in the top 10,
+/// - `exact` if it finished in exactly the predicted position,
+/// - `distance` minus one point per place it finished away from the prediction, never below 0.
+pub fn score_predictions(
+    predictions: &[PlacedSong],
+    official: &[OfficialResult],
+    rules: &ScoringRules,
+) -> Vec<LeaderboardEntry> {
+    let positions: HashMap<i32, i32> = official
+        .iter()
+        .map(|result| (result.song_id, result.position))
+        .collect();
+
+    // Keyed by user id, as two voters can share a name. Ordered so ties stay in a stable order.
+    let mut leaderboard: BTreeMap<i32, LeaderboardEntry> = BTreeMap::new();
+    for prediction in predictions {
+        let entry = leaderboard
+            .entry(prediction.user_id)
+            .or_insert_with(|| LeaderboardEntry {
+                user_name: prediction.user_name.clone(),
+                points: 0,
+                exact_hits: 0,
+                top_10_hits: 0,
+                in_list_hits: 0,
+                predictions: 0,
+            });
+        entry.predictions += 1;
+
+        let Some(actual) = positions.get(&prediction.song_id) else {
+            continue;
+        };
+
+        entry.in_list_hits += 1;
+        entry.points += rules.in_list;
+
+        if prediction.position <= 10 && *actual <= 10 {
+            entry.top_10_hits += 1;
+            entry.points += rules.top_10;
+        }
+
+        if prediction.position == *actual {
+            entry.exact_hits += 1;
+            entry.points += rules.exact;
+        }
+
+        let distance = (prediction.position - actual).abs() as i64;
+        entry.points += (rules.distance - distance).max(0);
+    }
+
+    let mut leaderboard: Vec<LeaderboardEntry> = leaderboard.into_values().collect();
+    leaderboard.sort_by(|a, b| {
+        b.points
+            .cmp(&a.points)
+            .then(b.exact_hits.cmp(&a.exact_hits))
+            .then(a.user_name.cmp(&b.user_name))
+    });
+    leaderboard
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn predict(user_id: i32, user_name: &str, song_id: i32, position: i32) -> PlacedSong {
+        PlacedSong {
+            user_id,
+            user_name: user_name.to_string(),
+            song_id,
+            position,
+        }
+    }
+
+    #[test]
+    fn predictions_earn_points_cumulatively() {
+        let rules = ScoringRules {
+            exact: 10,
+            top_10: 5,
+            in_list: 2,
+            distance: 5,
+        };
+        let official = [
+            OfficialResult {
+                song_id: 10,
+                position: 1,
+            },
+            OfficialResult {
+                song_id: 11,
+                position: 8,
+            },
+        ];
+        let predictions = [
+            predict(1, "alice", 10, 1),
+            predict(1, "alice", 11, 5),
+            predict(1, "alice", 12, 3),
+            predict(2, "bob", 11, 20),
+            // A different voter who happens to share a name
+            predict(3, "alice", 10, 2),
+        ];
+
+        let leaderboard = score_predictions(&predictions, &official, &rules);
+        let points: Vec<(&str, i64)> = leaderboard
+            .iter()
+            .map(|entry| (entry.user_name.as_str(), entry.points))
+            .collect();
+        assert_eq!(points, [("alice", 31), ("alice", 11), ("bob", 2)]);
+
+        let first = &leaderboard[0];
+        assert_eq!(first.predictions, 3);
+        assert_eq!(first.in_list_hits, 2);
+        assert_eq!(first.top_10_hits, 2);
+        assert_eq!(first.exact_hits, 1);
+    }
+}
@@ -0,0 +1,134 @@
+use crate::api::analytics::rank_points;
+use crate::api::db::{MusicTasteIndividual, RankedVote};
+use crate::api::types::{Recommendation, RecommendationReason, Song, TrackMetadata};
+use std::collections::{HashMap, HashSet};
+
+pub const DEFAULT_RECOMMENDATION_LIMIT: usize = 10;
+
+/// Collaborative filtering over our own ballots: every song a compatible voter ranked that the
+/// active user hasn't scores `combined_score * rank_points(rank) / 10`, summed over all the
+/// voters who picked it. Voters with a non-positive combined_score are ignored.
+///
+/// `already_ranked` is every canonical song on the active user's own ballot, draft or not.
+pub fn recommend_songs(
+    active_user_id: i32,
+    already_ranked: &HashSet<i32>,
+    matches: &[MusicTasteIndividual],
+    votes: &[RankedVote],
+    limit: usize,
+) -> Vec<Recommendation> {
+    let similarity: HashMap<i32, f64> = matches
+        .iter()
+        .filter(|m| m.combined_score > 0.0)
+        .map(|m| (m.other_user_id, m.combined_score))
+        .collect();
+
+    let mut candidates: HashMap<i32, Recommendation> = HashMap::new();
+    for vote in votes {
+        if vote.user_id == active_user_id || already_ranked.contains(&vote.song_id) {
+            continue;
+        }
+        let Some(combined_score) = similarity.get(&vote.user_id) else {
+            continue;
+        };
+
+        let entry = candidates
+            .entry(vote.song_id)
+            .or_insert_with(|| Recommendation {
+                song: Song {
+                    key: Some(format!("{}{}", vote.song_name, vote.artist)),
+                    name: vote.song_name.clone(),
+                    uri: vote.uri.clone(),
+                    artist: vote.artist.clone(),
+                    album_cover_url: vote.album_cover_url.clone(),
+                    release_date: None,
+                    release_date_precision: None,
+                    metadata: TrackMetadata::default(),
+                    rank: None,
+                },
+                score: 0.0,
+                recommended_by: Vec::new(),
+            });
+        entry.score += combined_score * rank_points(vote.rank) as f64 / 10.0;
+        entry.recommended_by.push(RecommendationReason {
+            user_name: vote.user_name.clone(),
+            rank: vote.rank,
+            combined_score: *combined_score,
+        });
+    }
+
+    let mut recommendations: Vec<Recommendation> = candidates.into_values().collect();
+    for recommendation in &mut recommendations {
+        recommendation.score = (recommendation.score * 100.0).round() / 100.0;
+        recommendation
+            .recommended_by
+            .sort_by(|a, b| b.combined_score.total_cmp(&a.combined_score));
+    }
+    recommendations.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then(a.song.name.cmp(&b.song.name))
+    });
+    recommendations.truncate(limit);
+    recommendations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn taste(other_user_id: i32, combined_score: f64) -> MusicTasteIndividual {
+        MusicTasteIndividual {
+            other_user_id,
+            other_user_name: format!("user {}", other_user_id),
+            combined_score,
+        }
+    }
+
+    fn vote(user_id: i32, song_id: i32, rank: i32) -> RankedVote {
+        RankedVote {
+            user_id,
+            user_name: format!("user {}", user_id),
+            song_id,
+            song_name: format!("Song {}", song_id),
+            artist: "Artist".to_string(),
+            artists: vec!["Artist".to_string()],
+            uri: format!("spotify:track:{}", song_id),
+            album_cover_url: String::new(),
+            rank,
+        }
+    }
+
+    #[test]
+    fn songs_are_scored_by_how_alike_their_voters_are() {
+        let matches = [taste(2, 20.0), taste(3, 10.0), taste(4, -5.0)];
+        let votes = [
+            vote(1, 10, 1),
+            vote(2, 10, 1),
+            vote(3, 10, 2),
+            vote(2, 11, 1),
+            vote(4, 12, 1),
+            vote(3, 13, 10),
+        ];
+        // Song 11 is only on the active user's draft, so it never shows up in their votes
+        let already_ranked = HashSet::from([10, 11]);
+
+        let recommendations = recommend_songs(1, &already_ranked, &matches, &votes, 10);
+        let scored: Vec<(&str, f64)> = recommendations
+            .iter()
+            .map(|recommendation| (recommendation.song.uri.as_str(), recommendation.score))
+            .collect();
+        assert_eq!(scored, [("spotify:track:13", 1.0)]);
+
+        let recommendations = recommend_songs(1, &HashSet::new(), &matches, &votes, 1);
+        assert_eq!(recommendations.len(), 1);
+        assert_eq!(recommendations[0].song.uri, "spotify:track:10");
+        assert_eq!(recommendations[0].score, 29.0);
+        let by: Vec<&str> = recommendations[0]
+            .recommended_by
+            .iter()
+            .map(|reason| reason.user_name.as_str())
+            .collect();
+        assert_eq!(by, ["user 2", "user 3"]);
+    }
+}
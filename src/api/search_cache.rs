@@ -0,0 +1,176 @@
+use crate::api::types::SearchPage;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+// Long enough to cover a voting session, short enough that new releases show up the same day
+pub const SEARCH_CACHE_TTL: Duration = Duration::from_secs(30 * 60);
+pub const SEARCH_CACHE_MAX_ENTRIES: usize = 1000;
+
+/// Everything that changes what Spotify returns for a search
+#[derive(Hash, PartialEq, Eq, Clone)]
+pub struct SearchKey {
+    query: String,
+    market: Option<String>,
+    offset: u32,
+    limit: u32,
+}
+
+impl SearchKey {
+    pub fn new(query: &str, market: Option<&str>, offset: u32, limit: u32) -> Self {
+        SearchKey {
+            query: query.trim().to_lowercase(),
+            market: market.map(str::to_uppercase),
+            offset,
+            limit,
+        }
+    }
+}
+
+struct CachedSearch {
+    page: SearchPage,
+    stored_at: Instant,
+}
+
+/// Spotify search results keyed on the query (trimmed and lowercased), market and page. Entries
+/// expire after the TTL, and the oldest entry makes way when the cache is full.
+pub struct SearchCache {
+    entries: HashMap<SearchKey, CachedSearch>,
+    ttl: Duration,
+    max_entries: usize,
+}
+
+impl SearchCache {
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        SearchCache {
+            entries: HashMap::new(),
+            ttl,
+            max_entries,
+        }
+    }
+
+    pub fn get(&mut self, key: &SearchKey) -> Option<SearchPage> {
+        match self.entries.get(key) {
+            Some(cached) if cached.stored_at.elapsed() < self.ttl => Some(cached.page.clone()),
+            Some(_) => {
+                self.entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn insert(&mut self, key: SearchKey, page: SearchPage) {
+        if self.entries.len() >= self.max_entries {
+            let ttl = self.ttl;
+            self.entries.retain(|_, cached| cached.stored_at.elapsed() < ttl);
+        }
+        if self.entries.len() >= self.max_entries {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, cached)| cached.stored_at)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        self.entries.insert(
+            key,
+            CachedSearch {
+                page,
+                stored_at: Instant::now(),
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread::sleep;
+
+    // A page that can be told apart from the others by its total
+    fn page(total: u32) -> SearchPage {
+        SearchPage {
+            songs: Vec::new(),
+            offset: 0,
+            limit: 10,
+            total,
+            next_offset: None,
+        }
+    }
+
+    fn key(query: &str) -> SearchKey {
+        SearchKey::new(query, None, 0, 10)
+    }
+
+    fn total(cache: &mut SearchCache, query: &str) -> Option<u32> {
+        cache.get(&key(query)).map(|page| page.total)
+    }
+
+    #[test]
+    fn keys_ignore_case_and_surrounding_space() {
+        let mut cache = SearchCache::new(SEARCH_CACHE_TTL, SEARCH_CACHE_MAX_ENTRIES);
+        cache.insert(SearchKey::new("  Flume ", Some("au"), 0, 10), page(1));
+
+        assert_eq!(
+            cache
+                .get(&SearchKey::new("flume", Some("AU"), 0, 10))
+                .map(|p| p.total),
+            Some(1)
+        );
+        assert!(cache
+            .get(&SearchKey::new("flume", Some("US"), 0, 10))
+            .is_none());
+        assert!(cache
+            .get(&SearchKey::new("flume", Some("AU"), 10, 10))
+            .is_none());
+    }
+
+    #[test]
+    fn entries_expire_after_the_ttl() {
+        let mut cache = SearchCache::new(Duration::from_millis(20), 10);
+        cache.insert(key("a"), page(1));
+        assert_eq!(total(&mut cache, "a"), Some(1));
+
+        sleep(Duration::from_millis(30));
+        assert_eq!(total(&mut cache, "a"), None);
+    }
+
+    #[test]
+    fn a_full_cache_evicts_the_oldest_entry() {
+        let mut cache = SearchCache::new(SEARCH_CACHE_TTL, 2);
+        cache.insert(key("a"), page(1));
+        sleep(Duration::from_millis(2));
+        cache.insert(key("b"), page(2));
+        sleep(Duration::from_millis(2));
+        cache.insert(key("c"), page(3));
+
+        assert_eq!(total(&mut cache, "a"), None);
+        assert_eq!(total(&mut cache, "b"), Some(2));
+        assert_eq!(total(&mut cache, "c"), Some(3));
+    }
+
+    #[test]
+    fn a_full_cache_drops_expired_entries_before_live_ones() {
+        let mut cache = SearchCache::new(Duration::from_millis(50), 2);
+        cache.insert(key("a"), page(1));
+        sleep(Duration::from_millis(60));
+        cache.insert(key("b"), page(2));
+        cache.insert(key("c"), page(3));
+
+        // "a" expired, so the full cache made room by dropping it rather than "b"
+        assert_eq!(total(&mut cache, "b"), Some(2));
+        assert_eq!(total(&mut cache, "c"), Some(3));
+    }
+
+    #[test]
+    fn inserting_a_cached_key_replaces_it() {
+        let mut cache = SearchCache::new(SEARCH_CACHE_TTL, 1);
+        cache.insert(key("a"), page(1));
+        cache.insert(key("a"), page(2));
+
+        assert_eq!(total(&mut cache, "a"), Some(2));
+    }
+}
@@ -0,0 +1,180 @@
+// Spotify's search endpoint caps both of these
+pub const MAX_SEARCH_LIMIT: u32 = 50;
+pub const MAX_SEARCH_OFFSET: u32 = 1000;
+pub const DEFAULT_SEARCH_LIMIT: u32 = 10;
+
+/// A `limit` or `offset` from the query string. Missing takes the default, anything that isn't
+/// a whole number is None.
+pub fn parse_count(value: Option<&str>, default: u32) -> Option<u32> {
+    match value.map(str::trim) {
+        None | Some("") => Some(default),
+        Some(value) => value.parse().ok(),
+    }
+}
+
+/// The track ID from a pasted link or URI, e.g. `https://open.spotify.com/track/<id>?si=...`,
+/// `open.spotify.com/intl-de/track/<id>` or `spotify:track:<id>`. None if it isn't one.
+pub fn parse_track_link(input: &str) -> Option<String> {
+    let input = input.trim();
+    let id = if let Some(id) = input.strip_prefix("spotify:track:") {
+        id
+    } else {
+        let path = input
+            .trim_start_matches("https://")
+            .trim_start_matches("http://")
+            .strip_prefix("open.spotify.com/")?;
+        let path = path.split(['?', '#']).next().unwrap_or_default();
+        let mut segments = path.split('/').skip_while(|segment| segment.starts_with("intl-"));
+        match (segments.next(), segments.next()) {
+            (Some("track"), Some(id)) => id,
+            _ => return None,
+        }
+    };
+
+    // Spotify IDs are base62
+    (!id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric())).then(|| id.to_string())
+}
+
+/// A market is an ISO 3166-1 alpha-2 country code, e.g. AU
+pub fn parse_market(market: &str) -> Option<String> {
+    let market = market.trim();
+    (market.len() == 2 && market.chars().all(|c| c.is_ascii_alphabetic()))
+        .then(|| market.to_uppercase())
+}
+
+/// A year filter is a year or an inclusive range, e.g. 2024 or 2020-2024
+pub fn valid_year(year: &str) -> bool {
+    let is_year = |part: &str| part.len() == 4 && part.chars().all(|c| c.is_ascii_digit());
+    match year.split_once('-') {
+        Some((from, to)) => is_year(from) && is_year(to) && from <= to,
+        None => is_year(year),
+    }
+}
+
+/// Builds the `q` for /v1/search from the typed text plus any field filters. Filters already
+/// typed into the text, like `artist:`, pass through as Spotify understands them.
+pub fn build_search_query(text: &str, artist: Option<&str>, year: Option<&str>) -> String {
+    let mut query = text.trim().to_string();
+    if let Some(artist) = artist.map(str::trim).filter(|artist| !artist.is_empty()) {
+        let artist = artist.replace('"', "");
+        if artist.contains(' ') {
+            query.push_str(&format!(" artist:\"{}\"", artist));
+        } else {
+            query.push_str(&format!(" artist:{}", artist));
+        }
+    }
+    if let Some(year) = year.map(str::trim).filter(|year| !year.is_empty()) {
+        query.push_str(&format!(" year:{}", year));
+    }
+    query.trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn track_links_and_uris_give_the_id() {
+        for input in [
+            "spotify:track:4uLU6hMCjMI75M1A2tKUQC",
+            "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC",
+            "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC?si=abc123",
+            "http://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC#top",
+            "open.spotify.com/intl-de/track/4uLU6hMCjMI75M1A2tKUQC?si=abc123",
+            "  https://open.spotify.com/intl-pt/track/4uLU6hMCjMI75M1A2tKUQC  ",
+        ] {
+            assert_eq!(
+                parse_track_link(input).as_deref(),
+                Some("4uLU6hMCjMI75M1A2tKUQC"),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn other_links_and_text_are_not_tracks() {
+        for input in [
+            "flume",
+            "spotify:track:",
+            "spotify:album:4uLU6hMCjMI75M1A2tKUQC",
+            "https://open.spotify.com/album/4uLU6hMCjMI75M1A2tKUQC",
+            "https://open.spotify.com/track/",
+            "https://open.spotify.com/track/not-an-id",
+            "https://example.com/track/4uLU6hMCjMI75M1A2tKUQC",
+        ] {
+            assert_eq!(parse_track_link(input), None, "{}", input);
+        }
+    }
+
+    #[test]
+    fn artist_and_year_filters_are_appended() {
+        assert_eq!(
+            build_search_query("  never be like you ", None, None),
+            "never be like you"
+        );
+        assert_eq!(
+            build_search_query("holdin on", Some("Flume"), None),
+            "holdin on artist:Flume"
+        );
+        assert_eq!(
+            build_search_query("tennis court", Some("  Lorde "), Some("2013")),
+            "tennis court artist:Lorde year:2013"
+        );
+        assert_eq!(
+            build_search_query("", None, Some("2020-2024")),
+            "year:2020-2024"
+        );
+        assert_eq!(build_search_query("song", Some(" "), Some(" ")), "song");
+    }
+
+    #[test]
+    fn artists_with_spaces_are_quoted_without_stray_quotes() {
+        assert_eq!(
+            build_search_query("elephant", Some("Tame Impala"), None),
+            "elephant artist:\"Tame Impala\""
+        );
+        assert_eq!(
+            build_search_query("song", Some("The \"Real\" Band"), None),
+            "song artist:\"The Real Band\""
+        );
+    }
+
+    #[test]
+    fn years_and_ranges() {
+        for year in ["2024", "2020-2024", "2024-2024"] {
+            assert!(valid_year(year), "{}", year);
+        }
+        for year in [
+            "",
+            "24",
+            "20x4",
+            "20245",
+            "2024-2020",
+            "2020-",
+            "-2024",
+            "2020-2024-2025",
+        ] {
+            assert!(!valid_year(year), "{}", year);
+        }
+    }
+
+    #[test]
+    fn counts_take_the_default_or_must_be_whole_numbers() {
+        assert_eq!(
+            parse_count(None, DEFAULT_SEARCH_LIMIT),
+            Some(DEFAULT_SEARCH_LIMIT)
+        );
+        assert_eq!(parse_count(Some(" "), 0), Some(0));
+        assert_eq!(parse_count(Some(" 25 "), 10), Some(25));
+        assert_eq!(parse_count(Some("lots"), 10), None);
+        assert_eq!(parse_count(Some("-1"), 10), None);
+    }
+
+    #[test]
+    fn markets_are_two_letter_codes() {
+        assert_eq!(parse_market(" au ").as_deref(), Some("AU"));
+        assert_eq!(parse_market("AUS"), None);
+        assert_eq!(parse_market("1A"), None);
+    }
+}
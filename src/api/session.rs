@@ -0,0 +1,60 @@
+use crate::api::error::AppError;
+use rocket::http::{Cookie, CookieJar};
+use rocket::serde::{Deserialize, Serialize};
+use rocket::time::Duration;
+
+// Matches the lifetime of the Spotify access token stored alongside it for Spotify users
+const SESSION_MINUTES: i64 = 60;
+
+/// The logged in user as stored, JSON encoded, in the private `user` cookie
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SessionUser {
+    pub provider: String,
+    pub provider_user_id: String,
+}
+
+impl SessionUser {
+    pub fn spotify(provider_user_id: &str) -> Self {
+        SessionUser {
+            provider: "spotify".to_string(),
+            provider_user_id: provider_user_id.to_string(),
+        }
+    }
+
+    pub fn guest(guest_id: &str) -> Self {
+        SessionUser {
+            provider: "guest".to_string(),
+            provider_user_id: guest_id.to_string(),
+        }
+    }
+
+    /// Reads the session from the cookies. Cookies set before identities were structured hold
+    /// a quoted URI instead, which doesn't parse, so those users just log in again.
+    pub fn from_cookies(cookies: &CookieJar<'_>) -> Option<Self> {
+        cookies
+            .get_private("user")
+            .and_then(|cookie| serde_json::from_str(cookie.value()).ok())
+    }
+
+    /// The session, or Unauthenticated for handlers that need someone logged in
+    pub fn require(cookies: &CookieJar<'_>) -> Result<Self, AppError> {
+        Self::from_cookies(cookies)
+            .ok_or_else(|| AppError::Unauthenticated("Log in to continue".to_string()))
+    }
+
+    pub fn save(&self, cookies: &CookieJar<'_>) {
+        let value = serde_json::to_string(self).expect("SessionUser always serializes");
+        cookies.add_private(
+            Cookie::build(("user", value))
+                .http_only(true)
+                .secure(true)
+                .max_age(Duration::minutes(SESSION_MINUTES)),
+        );
+    }
+
+    /// The name new users are stored with and admins are listed by, e.g. `spotify:user:abc`.
+    /// Users are looked up by provider and provider user ID, never by this.
+    pub fn name(&self) -> String {
+        format!("{}:user:{}", self.provider, self.provider_user_id)
+    }
+}
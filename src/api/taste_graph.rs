@@ -0,0 +1,180 @@
+use crate::api::db::{TastePair, User};
+use crate::api::types::{GraphLink, GraphNode, TasteGraph};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+pub const DEFAULT_GRAPH_MIN_SCORE: f64 = 0.0;
+pub const DEFAULT_GRAPH_TOP_K: usize = 5;
+
+/// Builds the user-to-user compatibility graph. Edges below `min_score` are dropped, then an
+/// edge is kept only if it is one of the `top_k` strongest edges of at least one of its two
+/// voters, so every voter keeps their best matches without the graph turning into a hairball.
+pub fn build_taste_graph(
+    voters: &[User],
+    pairs: &[TastePair],
+    min_score: f64,
+    top_k: usize,
+) -> TasteGraph {
+    let candidates: Vec<&TastePair> = pairs
+        .iter()
+        .filter(|pair| pair.combined_score > 0.0 && pair.combined_score >= min_score)
+        .collect();
+
+    let mut per_node: HashMap<i32, Vec<(usize, f64)>> = HashMap::new();
+    for (index, pair) in candidates.iter().enumerate() {
+        per_node
+            .entry(pair.user1_id)
+            .or_default()
+            .push((index, pair.combined_score));
+        per_node
+            .entry(pair.user2_id)
+            .or_default()
+            .push((index, pair.combined_score));
+    }
+
+    let mut kept: HashSet<usize> = HashSet::new();
+    for edges in per_node.values_mut() {
+        edges.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        kept.extend(edges.iter().take(top_k).map(|(index, _)| *index));
+    }
+
+    let links = candidates
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| kept.contains(index))
+        .map(|(_, pair)| GraphLink {
+            source: pair.user1_id,
+            target: pair.user2_id,
+            weight: pair.combined_score,
+        })
+        .collect();
+
+    let nodes = voters
+        .iter()
+        .map(|voter| GraphNode {
+            id: voter.id,
+            label: voter.label.clone(),
+        })
+        .collect();
+
+    TasteGraph { nodes, links }
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn escape_dot(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+pub fn to_graphml(graph: &TasteGraph) -> String {
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n");
+    out.push_str("  <key id=\"label\" for=\"node\" attr.name=\"label\" attr.type=\"string\"/>\n");
+    out.push_str("  <key id=\"weight\" for=\"edge\" attr.name=\"weight\" attr.type=\"double\"/>\n");
+    out.push_str("  <graph id=\"taste\" edgedefault=\"undirected\">\n");
+    for node in &graph.nodes {
+        let _ = writeln!(
+            out,
+            "    <node id=\"u{}\"><data key=\"label\">{}</data></node>",
+            node.id,
+            escape_xml(&node.label)
+        );
+    }
+    for link in &graph.links {
+        let _ = writeln!(
+            out,
+            "    <edge source=\"u{}\" target=\"u{}\"><data key=\"weight\">{}</data></edge>",
+            link.source, link.target, link.weight
+        );
+    }
+    out.push_str("  </graph>\n");
+    out.push_str("</graphml>\n");
+    out
+}
+
+pub fn to_dot(graph: &TasteGraph) -> String {
+    let mut out = String::from("graph taste {\n");
+    for node in &graph.nodes {
+        let _ = writeln!(
+            out,
+            "  u{} [label=\"{}\"];",
+            node.id,
+            escape_dot(&node.label)
+        );
+    }
+    for link in &graph.links {
+        let _ = writeln!(
+            out,
+            "  u{} -- u{} [weight={}, label=\"{}\"];",
+            link.source, link.target, link.weight, link.weight
+        );
+    }
+    out.push_str("}\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn voter(id: i32, label: &str) -> User {
+        User {
+            id,
+            name: format!("spotify:user:{}", id),
+            label: label.to_string(),
+        }
+    }
+
+    fn pair(user1_id: i32, user2_id: i32, combined_score: f64) -> TastePair {
+        TastePair {
+            user1_id,
+            user1_name: String::new(),
+            user2_id,
+            user2_name: String::new(),
+            combined_score,
+        }
+    }
+
+    fn links(graph: &TasteGraph) -> Vec<(i32, i32)> {
+        graph
+            .links
+            .iter()
+            .map(|link| (link.source, link.target))
+            .collect()
+    }
+
+    #[test]
+    fn each_voter_keeps_their_strongest_edges() {
+        let voters = [voter(1, "a"), voter(2, "b"), voter(3, "c"), voter(4, "d")];
+        let pairs = [
+            pair(1, 2, 30.0),
+            pair(1, 3, 20.0),
+            pair(2, 3, 10.0),
+            pair(3, 4, 5.0),
+            pair(1, 4, 0.0),
+        ];
+
+        // 2-3 is nobody's best match and 1-4 has nothing in common
+        let graph = build_taste_graph(&voters, &pairs, DEFAULT_GRAPH_MIN_SCORE, 1);
+        assert_eq!(links(&graph), [(1, 2), (1, 3), (3, 4)]);
+        assert_eq!(graph.nodes.len(), 4);
+
+        let graph = build_taste_graph(&voters, &pairs, 15.0, DEFAULT_GRAPH_TOP_K);
+        assert_eq!(links(&graph), [(1, 2), (1, 3)]);
+    }
+
+    #[test]
+    fn exports_escape_labels() {
+        let graph = build_taste_graph(&[voter(1, "Bo \"B\" & <co>")], &[], 0.0, 1);
+        assert!(to_graphml(&graph).contains("Bo &quot;B&quot; &amp; &lt;co&gt;"));
+        assert!(to_dot(&graph).contains(r#"u1 [label="Bo \"B\" & <co>"];"#));
+    }
+}
@@ -1,30 +1,178 @@
+use chrono::{DateTime, NaiveDate, Utc};
 use rocket::serde::{Deserialize, Serialize};
 
 #[derive(Deserialize)]
 pub struct AccessTokenResponse {
     pub(crate) access_token: String,
+    // Seconds until the token expires
+    #[serde(default)]
+    pub(crate) expires_in: Option<u64>,
 }
 
 // Struct to parse the query parameters
 #[derive(FromForm)]
 pub struct SearchSongsQuery {
+    // Free text, which may carry Spotify field filters, or a pasted track link
     pub(crate) track: Option<String>,
     pub(crate) rank: Option<i32>,
+    pub(crate) artist: Option<String>,
+    // A year or a range, e.g. 2020-2024
+    pub(crate) year: Option<String>,
+    pub(crate) market: Option<String>,
+    // Kept as text, since a number that fails to parse in an Option field is silently dropped
+    pub(crate) offset: Option<String>,
+    pub(crate) limit: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// One page of search results. `next_offset` is None on the last page.
+#[derive(Serialize, Debug, Clone)]
+pub struct SearchPage {
+    pub songs: Vec<Song>,
+    pub offset: u32,
+    pub limit: u32,
+    pub total: u32,
+    pub next_offset: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TrackArtist {
+    pub id: Option<String>,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TrackImage {
+    pub url: String,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+}
+
+// Everything else Spotify tells us about a track, filled in on search or by the backfill job
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TrackMetadata {
+    #[serde(default)]
+    pub isrc: Option<String>,
+    #[serde(default)]
+    pub album_name: Option<String>,
+    #[serde(default)]
+    pub artists: Vec<TrackArtist>,
+    #[serde(default)]
+    pub duration_ms: Option<i32>,
+    #[serde(default)]
+    pub explicit: Option<bool>,
+    #[serde(default)]
+    pub popularity: Option<i32>,
+    #[serde(default)]
+    pub preview_url: Option<String>,
+    #[serde(default)]
+    pub images: Vec<TrackImage>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Song {
     pub key: Option<String>,
     pub name: String,
     pub uri: String,
     pub artist: String,
     pub album_cover_url: String,
+    #[serde(default)]
+    pub release_date: Option<String>,
+    #[serde(default)]
+    pub release_date_precision: Option<String>,
+    #[serde(flatten, default)]
+    pub metadata: TrackMetadata,
     pub rank: Option<i32>
 }
 
+#[derive(Serialize, Debug)]
+pub struct MetadataBackfill {
+    pub updated: usize,
+    pub not_found: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    // applied, pending, modified (checksum differs) or unknown (not in this build)
+    pub state: String,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct CanonicalScan {
+    pub linked: usize,
+    pub suggested: usize,
+}
+
+#[derive(Serialize, Debug)]
+pub struct MergeCandidate {
+    pub id: i32,
+    pub name: String,
+    pub artist: String,
+    pub uri: String,
+    pub album_name: Option<String>,
+    pub isrc: Option<String>,
+    pub votes: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MergedRanking {
+    pub user_id: i32,
+    pub song_id: i32,
+    pub rank: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MergedPrediction {
+    pub poll_id: i32,
+    pub user_id: i32,
+    pub song_id: i32,
+    pub position: i32,
+}
+
+#[derive(Deserialize)]
+pub struct SongMergeRequest {
+    pub from_song_id: i32,
+    pub into_song_id: i32,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SongMerge {
+    pub id: i32,
+    pub from_song_id: i32,
+    pub into_song_id: i32,
+    pub moved_rankings: Vec<MergedRanking>,
+    pub dropped_rankings: Vec<MergedRanking>,
+    pub merged_by: String,
+    pub merged_at: DateTime<Utc>,
+    pub undone_by: Option<String>,
+    pub undone_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SongMergeUndo {
+    pub restored: usize,
+    pub skipped: usize,
+}
+
+#[derive(Serialize, Debug)]
+pub struct MergeSuggestion {
+    pub id: i32,
+    pub reason: String,
+    pub status: String,
+    pub reviewed_by: Option<String>,
+    pub song: MergeCandidate,
+    pub canonical: MergeCandidate,
+}
+
+/// The body of every error response. `code` is stable for clients to branch on, `error` is
+/// for people, and `errors` lists each broken rule when a ballot is rejected.
 #[derive(Serialize, Debug)]
 pub struct ErrorResponse {
+    pub(crate) code: &'static str,
     pub(crate) error: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) errors: Vec<BallotError>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -60,3 +208,357 @@ pub struct MusicTasteOverview {
     pub overlapping_song_details: Option<serde_json::Value>,
     pub overlapping_artist_details: Option<serde_json::Value>,
 }
+
+#[derive(Serialize, Debug)]
+pub struct ClusterSong {
+    pub name: String,
+    pub artist: String,
+    pub votes: i64,
+    pub points: i64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ClusterArtist {
+    pub artist: String,
+    pub votes: i64,
+    pub points: i64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TasteCluster {
+    pub id: usize,
+    pub members: Vec<String>,
+    pub cohesion: Option<f64>,
+    pub defining_songs: Vec<ClusterSong>,
+    pub defining_artists: Vec<ClusterArtist>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct GraphNode {
+    pub id: i32,
+    pub label: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct GraphLink {
+    pub source: i32,
+    pub target: i32,
+    pub weight: f64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TasteGraph {
+    pub nodes: Vec<GraphNode>,
+    pub links: Vec<GraphLink>,
+}
+
+#[derive(FromFormField, Debug, Default, Clone, Copy)]
+pub enum GraphFormat {
+    #[default]
+    Json,
+    Graphml,
+    Dot,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RecommendationReason {
+    pub user_name: String,
+    pub rank: i32,
+    pub combined_score: f64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Recommendation {
+    pub song: Song,
+    pub score: f64,
+    pub recommended_by: Vec<RecommendationReason>,
+}
+
+#[derive(Serialize, Debug, sqlx::FromRow)]
+pub struct Poll {
+    pub id: i32,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub closed_at: Option<DateTime<Utc>>,
+    pub eligible_from: Option<NaiveDate>,
+    pub eligible_to: Option<NaiveDate>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct WrappedSong {
+    pub name: String,
+    pub artist: String,
+    pub rank: i32,
+    pub position: Option<i32>,
+    pub votes: i64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TasteTwin {
+    pub user_name: String,
+    pub combined_score: f64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ReturningSong {
+    pub name: String,
+    pub artist: String,
+    pub previous_rank: i32,
+    pub rank: i32,
+}
+
+#[derive(Serialize, Debug)]
+pub struct YearOverYear {
+    pub previous_poll: String,
+    pub previous_picks_in_countdown: usize,
+    pub picks_in_countdown_change: i64,
+    pub previous_best_position: Option<i32>,
+    pub returning_songs: Vec<ReturningSong>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct WrappedSummary {
+    pub user_name: String,
+    pub poll: String,
+    pub countdown_size: usize,
+    pub picks: Vec<WrappedSong>,
+    pub picks_in_countdown: usize,
+    pub called_it: Option<WrappedSong>,
+    pub most_contrarian: Option<WrappedSong>,
+    pub taste_twin: Option<TasteTwin>,
+    pub year_over_year: Option<YearOverYear>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OfficialResultEntry {
+    pub position: i32,
+    pub uri: Option<String>,
+    pub name: Option<String>,
+    pub artist: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct OfficialResultsImport {
+    pub matched: usize,
+    pub unmatched: Vec<String>,
+}
+
+// Points for each predicted song, see predictions::score_predictions
+#[derive(FromForm, Debug, Clone, Copy)]
+pub struct ScoringRules {
+    #[field(default = 10)]
+    pub exact: i64,
+    #[field(default = 5)]
+    pub top_10: i64,
+    #[field(default = 2)]
+    pub in_list: i64,
+    #[field(default = 5)]
+    pub distance: i64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct LeaderboardEntry {
+    pub user_name: String,
+    pub points: i64,
+    pub exact_hits: i64,
+    pub top_10_hits: i64,
+    pub in_list_hits: i64,
+    pub predictions: i64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ComparedSong {
+    pub name: String,
+    pub artist: String,
+    pub group_position: Option<i32>,
+    pub official_position: Option<i32>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CountdownComparison {
+    pub poll: String,
+    pub group_size: usize,
+    pub official_size: usize,
+    pub overlap: usize,
+    pub biggest_agreements: Vec<ComparedSong>,
+    pub loved_but_missed: Vec<ComparedSong>,
+    pub rank_correlation: Option<f64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct EligibilityWindow {
+    pub eligible_from: Option<NaiveDate>,
+    pub eligible_to: Option<NaiveDate>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct EligibilityOverride {
+    pub uri: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct EligibilityFlag {
+    pub name: String,
+    pub artist: String,
+    pub rank: Option<i32>,
+    pub reason: String,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct SaveSongsResponse {
+    pub flagged: Vec<EligibilityFlag>,
+}
+
+/// Per-poll ballot rules, stored as JSON on the poll. Missing fields take the defaults.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct BallotRules {
+    pub unique_ranks: bool,
+    pub unique_songs: bool,
+    pub contiguous_ranks: bool,
+    pub max_per_artist: Option<usize>,
+    // Reject songs released outside the eligibility window rather than just flagging them
+    pub eligible_only: bool,
+    pub min_songs: usize,
+    pub max_songs: usize,
+}
+
+impl Default for BallotRules {
+    fn default() -> Self {
+        BallotRules {
+            unique_ranks: true,
+            unique_songs: true,
+            contiguous_ranks: false,
+            max_per_artist: None,
+            eligible_only: true,
+            min_songs: 1,
+            max_songs: 10,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct BallotError {
+    pub row: Option<usize>,
+    pub rank: Option<i32>,
+    pub uri: Option<String>,
+    pub rule: String,
+    pub message: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BallotStatus {
+    pub status: String,
+    pub version: i32,
+    pub updated_at: DateTime<Utc>,
+    pub submitted_at: Option<DateTime<Utc>>,
+    pub songs: i64,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct BallotSubmissions {
+    pub submitted: usize,
+    pub drafts: usize,
+    pub submitted_voters: Vec<String>,
+    pub draft_voters: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RevisionEntry {
+    pub rank: i32,
+    pub uri: String,
+    pub name: String,
+    pub artist: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BallotRevision {
+    pub version: i32,
+    pub poll_id: Option<i32>,
+    pub status: String,
+    pub songs: Vec<RevisionEntry>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BallotChangeKind {
+    Added,
+    Removed,
+    Moved,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BallotChange {
+    pub kind: BallotChangeKind,
+    pub uri: String,
+    pub name: String,
+    pub artist: String,
+    pub from_rank: Option<i32>,
+    pub to_rank: Option<i32>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BallotDiff {
+    pub user_name: String,
+    pub from_version: i32,
+    pub from_created_at: DateTime<Utc>,
+    pub to_version: i32,
+    pub to_created_at: DateTime<Utc>,
+    pub changes: Vec<BallotChange>,
+}
+
+/// Who a user is as shown across the site. `label` is the nickname, else the Spotify display
+/// name, else the raw Spotify URI.
+#[derive(Serialize, Debug, sqlx::FromRow)]
+pub struct UserProfile {
+    pub name: String,
+    pub display_name: Option<String>,
+    pub avatar_url: Option<String>,
+    pub nickname: Option<String>,
+    pub label: String,
+}
+
+/// A blank or missing nickname clears it
+#[derive(Deserialize, Debug)]
+pub struct NicknameRequest {
+    pub nickname: Option<String>,
+}
+
+#[derive(Serialize, Debug, sqlx::FromRow)]
+pub struct GuestInvite {
+    pub id: i32,
+    pub code: String,
+    // None means unlimited
+    pub max_uses: Option<i32>,
+    pub uses: i32,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GuestInviteRequest {
+    pub max_uses: Option<i32>,
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GuestJoinRequest {
+    pub code: String,
+    pub name: String,
+    pub pin: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GuestLoginRequest {
+    pub guest_id: String,
+    pub pin: String,
+}
+
+/// `guest_id` and the PIN are what a guest logs back in with
+#[derive(Serialize, Debug)]
+pub struct GuestLogin {
+    pub guest_id: String,
+    pub profile: UserProfile,
+}
@@ -0,0 +1,187 @@
+use crate::api::db::{MusicTasteIndividual, PollPick};
+use crate::api::types::{
+    Poll, ReturningSong, TasteTwin, WrappedSong, WrappedSummary, YearOverYear,
+};
+use std::collections::HashMap;
+
+pub const COUNTDOWN_SIZE: usize = 100;
+
+fn made_it(position: Option<i32>) -> bool {
+    position.is_some_and(|position| position as usize <= COUNTDOWN_SIZE)
+}
+
+/// Builds the end-of-poll summary for one voter from their ballot in a closed poll.
+///
+/// - `called_it` is their pick that finished highest in the countdown.
+/// - `most_contrarian` is their pick the fewest other voters chose, ties going to the pick
+///   that finished lowest.
+/// - `taste_twin` is their best match from get_music_taste_user.
+/// - `year_over_year` compares against their ballot in the poll that closed before it.
+///
+/// Picks, votes and earlier picks are all matched on the canonical song id, so a variant of
+/// a song counts as that song.
+pub fn build_wrapped_summary(
+    user_name: &str,
+    poll: &Poll,
+    picks: &[PollPick],
+    countdown_size: usize,
+    vote_counts: &HashMap<i32, i64>,
+    matches: &[MusicTasteIndividual],
+    previous: Option<(Poll, Vec<PollPick>)>,
+) -> WrappedSummary {
+    let mut wrapped_picks: Vec<WrappedSong> = picks
+        .iter()
+        .map(|pick| WrappedSong {
+            name: pick.name.clone(),
+            artist: pick.artist.clone(),
+            rank: pick.rank,
+            position: pick.position.filter(|position| made_it(Some(*position))),
+            votes: vote_counts.get(&pick.song_id).copied().unwrap_or(0),
+        })
+        .collect();
+    wrapped_picks.sort_by_key(|song| song.rank);
+
+    let picks_in_countdown = wrapped_picks
+        .iter()
+        .filter(|song| made_it(song.position))
+        .count();
+
+    let called_it = wrapped_picks
+        .iter()
+        .filter(|song| made_it(song.position))
+        .min_by_key(|song| (song.position, song.rank))
+        .cloned();
+
+    let most_contrarian = wrapped_picks
+        .iter()
+        .min_by_key(|song| {
+            (
+                song.votes,
+                std::cmp::Reverse(song.position.unwrap_or(i32::MAX)),
+                song.rank,
+            )
+        })
+        .cloned();
+
+    let taste_twin = matches.first().map(|m| TasteTwin {
+        user_name: m.other_user_name.clone(),
+        combined_score: m.combined_score,
+    });
+
+    let year_over_year = previous.map(|(poll, previous_picks)| {
+        let previous_picks_in_countdown = previous_picks
+            .iter()
+            .filter(|pick| made_it(pick.position))
+            .count();
+        let current_ranks: HashMap<i32, i32> =
+            picks.iter().map(|pick| (pick.song_id, pick.rank)).collect();
+
+        YearOverYear {
+            previous_poll: poll.name,
+            previous_picks_in_countdown,
+            picks_in_countdown_change: picks_in_countdown as i64
+                - previous_picks_in_countdown as i64,
+            previous_best_position: previous_picks.iter().filter_map(|pick| pick.position).min(),
+            returning_songs: previous_picks
+                .iter()
+                .filter_map(|pick| {
+                    Some(ReturningSong {
+                        name: pick.name.clone(),
+                        artist: pick.artist.clone(),
+                        previous_rank: pick.rank,
+                        rank: *current_ranks.get(&pick.song_id)?,
+                    })
+                })
+                .collect(),
+        }
+    });
+
+    WrappedSummary {
+        user_name: user_name.to_string(),
+        poll: poll.name.clone(),
+        countdown_size: countdown_size.min(COUNTDOWN_SIZE),
+        picks: wrapped_picks,
+        picks_in_countdown,
+        called_it,
+        most_contrarian,
+        taste_twin,
+        year_over_year,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn poll(name: &str) -> Poll {
+        Poll {
+            id: 1,
+            name: name.to_string(),
+            created_at: Utc::now(),
+            closed_at: Some(Utc::now()),
+            eligible_from: None,
+            eligible_to: None,
+        }
+    }
+
+    fn pick(song_id: i32, rank: i32, position: Option<i32>) -> PollPick {
+        PollPick {
+            song_id,
+            name: format!("Song {}", song_id),
+            artist: "Artist".to_string(),
+            rank,
+            position,
+        }
+    }
+
+    #[test]
+    fn picks_are_scored_against_the_countdown() {
+        let picks = [pick(3, 3, None), pick(1, 1, Some(5)), pick(2, 2, Some(120))];
+        let votes = HashMap::from([(1, 10), (2, 1), (3, 1)]);
+        let matches = [MusicTasteIndividual {
+            other_user_id: 2,
+            other_user_name: "bob".to_string(),
+            combined_score: 12.5,
+        }];
+
+        let summary =
+            build_wrapped_summary("alice", &poll("2026"), &picks, 150, &votes, &matches, None);
+        assert_eq!(summary.poll, "2026");
+        assert_eq!(summary.countdown_size, COUNTDOWN_SIZE);
+        let ranks: Vec<i32> = summary.picks.iter().map(|song| song.rank).collect();
+        assert_eq!(ranks, [1, 2, 3]);
+        // #120 finished outside the countdown, so it doesn't count
+        assert_eq!(summary.picks[1].position, None);
+        assert_eq!(summary.picks_in_countdown, 1);
+        assert_eq!(summary.called_it.unwrap().name, "Song 1");
+        assert_eq!(summary.most_contrarian.unwrap().name, "Song 2");
+        assert_eq!(summary.taste_twin.unwrap().user_name, "bob");
+        assert!(summary.year_over_year.is_none());
+    }
+
+    #[test]
+    fn the_previous_poll_is_compared_on_canonical_songs() {
+        let picks = [pick(1, 1, Some(5)), pick(3, 2, None)];
+        let previous = vec![pick(3, 1, Some(40)), pick(9, 2, Some(2))];
+
+        let summary = build_wrapped_summary(
+            "alice",
+            &poll("2026"),
+            &picks,
+            100,
+            &HashMap::new(),
+            &[],
+            Some((poll("2025"), previous)),
+        );
+        let year_over_year = summary.year_over_year.unwrap();
+        assert_eq!(year_over_year.previous_poll, "2025");
+        assert_eq!(year_over_year.previous_picks_in_countdown, 2);
+        assert_eq!(year_over_year.picks_in_countdown_change, -1);
+        assert_eq!(year_over_year.previous_best_position, Some(2));
+        assert_eq!(year_over_year.returning_songs.len(), 1);
+        assert_eq!(year_over_year.returning_songs[0].name, "Song 3");
+        assert_eq!(year_over_year.returning_songs[0].previous_rank, 1);
+        assert_eq!(year_over_year.returning_songs[0].rank, 2);
+    }
+}
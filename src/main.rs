@@ -4,12 +4,12 @@ mod api;
 
 use dotenv::dotenv;
 use reqwest::Client;
-use rocket::fairing::{AdHoc, Fairing, Info, Kind};
+use rocket::fairing::AdHoc;
 use rocket::fs::FileServer;
 use rocket::tokio::sync::OnceCell;
-use sqlx::{ConnectOptions, Connection};
+use rocket::{Build, Rocket};
 use sqlx_postgres::{PgPool, PgPoolOptions};
-use crate::api::{external_api, internal_api};
+use crate::api::{db, external_api, internal_api};
 
 static DB_POOL: OnceCell<PgPool> = OnceCell::const_new();
 
@@ -23,21 +23,70 @@ async fn init_pool() -> PgPool {
 
 }
 
-#[launch]
-fn rocket() -> _ {
+// `--migrate-only` applies pending migrations and exits, `--migration-status` only reports.
+// Both print every migration's state and exit non-zero if any isn't applied.
+async fn migrate_only(apply: bool) {
+    let pool = init_pool().await;
+    if apply {
+        db::run_migrations(&pool)
+            .await
+            .expect("Failed to run database migrations");
+    }
+
+    let statuses = db::get_migration_status(&pool)
+        .await
+        .expect("Failed to read migration status");
+    for status in &statuses {
+        println!("{:>4} {:<8} {}", status.version, status.state, status.description);
+    }
+    if statuses.iter().any(|status| status.state != "applied") {
+        std::process::exit(1);
+    }
+}
+
+#[rocket::main]
+async fn main() {
     dotenv().ok();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--migrate-only") {
+        migrate_only(true).await;
+        return;
+    }
+    if args.iter().any(|arg| arg == "--migration-status") {
+        migrate_only(false).await;
+        return;
+    }
+
+    // Launch errors report themselves when dropped, as with #[launch]
+    let _ = rocket().launch().await;
+}
+
+fn rocket() -> Rocket<Build> {
     let static_dir = std::env::var("STATIC_DIR").expect("STATIC_DIR must be set");
 
     let figment = rocket::Config::figment()
         .merge(("port", 8080))
         .merge(("address", "0.0.0.0"));
-    rocket::custom(figment)
-        .attach(AdHoc::on_ignite("Database Pool", |rocket| async {
+    app(rocket::custom(figment), &static_dir)
+        .attach(AdHoc::try_on_ignite("Database Pool", |rocket| async {
             let pool = init_pool().await;
+            if let Err(err) = db::run_migrations(&pool).await {
+                rocket::error!("Failed to run database migrations: {}", err);
+                return Err(rocket);
+            }
             DB_POOL.set(pool).unwrap();
-            rocket }))
+            Ok(rocket) }))
+}
+
+// Everything but the database, so tests can drive the routes without one
+fn app(rocket: Rocket<Build>, static_dir: &str) -> Rocket<Build> {
+    rocket
         .manage(Client::new())
-        .mount("/", routes![internal_api::index, internal_api::login_page, external_api::callback, internal_api::main_page, internal_api::files, internal_api::search_songs, internal_api::save_songs, internal_api::get_songs, internal_api::generate_playlist, internal_api::get_music_taste])
+        .mount("/", routes![internal_api::index, internal_api::login_page, external_api::callback, internal_api::main_page, internal_api::files, internal_api::search_songs, internal_api::save_songs, internal_api::get_songs, internal_api::generate_playlist, internal_api::get_music_taste, internal_api::get_taste_clusters, internal_api::get_taste_graph, internal_api::get_recommendations, internal_api::get_voters, internal_api::generate_blend, internal_api::open_poll, internal_api::close_poll, internal_api::get_wrapped, internal_api::save_predictions, internal_api::get_predictions, internal_api::load_official_results, internal_api::load_official_results_csv, internal_api::compare_official_results, internal_api::get_prediction_leaderboard, internal_api::set_poll_eligibility, internal_api::get_eligibility_overrides, internal_api::add_eligibility_override, internal_api::remove_eligibility_override, internal_api::backfill_song_metadata, internal_api::scan_canonical_songs, internal_api::get_merge_suggestions, internal_api::confirm_merge_suggestion, internal_api::reject_merge_suggestion, internal_api::merge_songs, internal_api::get_song_merges, internal_api::undo_song_merge, internal_api::split_song, internal_api::get_ballot_rules, internal_api::set_ballot_rules, internal_api::get_ballot, internal_api::submit_ballot, internal_api::unsubmit_ballot, internal_api::get_ballot_submissions, internal_api::get_ballot_history, internal_api::get_ballot_diff, internal_api::replace_ballot, internal_api::remove_ballot_entry, internal_api::move_ballot_entry, internal_api::get_migration_status, internal_api::get_profile, internal_api::set_nickname, internal_api::guest_page, internal_api::join_as_guest, internal_api::guest_login, internal_api::search_catalogue, internal_api::create_guest_invite, internal_api::get_guest_invites, internal_api::revoke_guest_invite])
         .mount("/main", FileServer::from(static_dir))
-
+        .register("/", catchers![internal_api::catch_error])
 }
+
+#[cfg(test)]
+mod tests;
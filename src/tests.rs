@@ -0,0 +1,434 @@
+// Every request here is turned away before it reaches the database or Spotify, so the app is
+// built without its database pool and no network calls are made. The with_database tests at the
+// end are the exception and only run when TEST_DATABASE_URL is set.
+use super::app;
+use rocket::http::{ContentType, Cookie, Header, Status};
+use rocket::local::blocking::{Client, LocalRequest, LocalResponse};
+use serde_json::{json, Value};
+
+const ADMIN: &str = "spotify:user:admin";
+
+fn client() -> Client {
+    // Every test sets the same value, so running them in parallel is fine
+    std::env::set_var("ADMIN_USERS", ADMIN);
+    Client::tracked(app(rocket::build(), "static")).expect("valid rocket instance")
+}
+
+fn logged_in<'c>(request: LocalRequest<'c>, provider_user_id: &str) -> LocalRequest<'c> {
+    let session = json!({ "provider": "spotify", "provider_user_id": provider_user_id });
+    request.private_cookie(Cookie::new("user", session.to_string()))
+}
+
+fn as_admin(request: LocalRequest<'_>) -> LocalRequest<'_> {
+    logged_in(request, ADMIN.trim_start_matches("spotify:user:"))
+}
+
+#[track_caller]
+fn assert_error(response: LocalResponse<'_>, status: Status, code: &str) {
+    assert_eq!(response.status(), status);
+    assert_eq!(response.content_type(), Some(ContentType::JSON));
+    let body: Value = response.into_json().expect("JSON error body");
+    assert_eq!(body["code"], code, "{}", body);
+    assert!(body["error"].as_str().is_some_and(|error| !error.is_empty()));
+}
+
+#[test]
+fn routes_that_need_a_session_return_401_without_one() {
+    let client = client();
+    for uri in [
+        "/songs",
+        "/ballot",
+        "/ballot/history",
+        "/ballot/history/diff",
+        "/profile",
+        "/wrapped",
+        "/predictions",
+        "/recommendations",
+        "/generate_blend?user=spotify:user:bob",
+        "/search-songs?track=flume",
+        "/catalogue?track=flume",
+    ] {
+        assert_error(client.get(uri).dispatch(), Status::Unauthorized, "unauthenticated");
+    }
+    for uri in ["/ballot/submit", "/ballot/unsubmit", "/admin/polls/close"] {
+        assert_error(client.post(uri).dispatch(), Status::Unauthorized, "unauthenticated");
+    }
+    assert_error(
+        client.delete("/ballot/entries/1").dispatch(),
+        Status::Unauthorized,
+        "unauthenticated",
+    );
+}
+
+#[test]
+fn a_cookie_that_is_not_a_session_is_unauthenticated() {
+    let client = client();
+    let response = client
+        .get("/profile")
+        .private_cookie(Cookie::new("user", "\"spotify:user:alice\""))
+        .dispatch();
+    assert_error(response, Status::Unauthorized, "unauthenticated");
+}
+
+#[test]
+fn admin_routes_return_403_for_other_users() {
+    let client = client();
+    for uri in ["/admin/migrations", "/admin/guest-invites", "/admin/songs/merges"] {
+        let response = logged_in(client.get(uri), "alice").dispatch();
+        assert_error(response, Status::Forbidden, "forbidden");
+    }
+    let response = logged_in(client.post("/admin/polls/close"), "alice").dispatch();
+    assert_error(response, Status::Forbidden, "forbidden");
+}
+
+#[test]
+fn search_rejects_bad_queries() {
+    let client = client();
+    for uri in [
+        "/search-songs",
+        "/search-songs?track=%20%20",
+        "/search-songs?track=flume&limit=0",
+        "/search-songs?track=flume&limit=51",
+        "/search-songs?track=flume&limit=lots",
+        "/search-songs?track=flume&offset=5000",
+        "/search-songs?track=flume&market=AUS",
+        "/search-songs?track=flume&year=20x4",
+        "/search-songs?track=flume&year=2024-2020",
+    ] {
+        let response = logged_in(client.get(uri), "alice").dispatch();
+        assert_error(response, Status::BadRequest, "invalid_request");
+    }
+}
+
+#[test]
+fn catalogue_search_needs_a_track() {
+    let client = client();
+    let response = logged_in(client.get("/catalogue?track=%20"), "alice").dispatch();
+    assert_error(response, Status::BadRequest, "invalid_request");
+}
+
+#[test]
+fn ballot_writes_reject_malformed_bodies() {
+    let client = client();
+    for body in ["not json", "[{\"name\": 1}]", "{\"rank\": 1}"] {
+        let response = logged_in(client.put("/ballot"), "alice")
+            .header(ContentType::JSON)
+            .body(body)
+            .dispatch();
+        assert_error(response, Status::BadRequest, "invalid_request");
+
+        let response = logged_in(client.post("/songs"), "alice")
+            .header(ContentType::JSON)
+            .body(body)
+            .dispatch();
+        assert_error(response, Status::BadRequest, "invalid_request");
+    }
+}
+
+#[test]
+fn ballot_writes_reject_a_bad_if_match() {
+    let client = client();
+    let response = logged_in(client.put("/ballot"), "alice")
+        .header(ContentType::JSON)
+        .header(Header::new("If-Match", "\"latest\""))
+        .body("[]")
+        .dispatch();
+    assert_error(response, Status::BadRequest, "invalid_request");
+}
+
+#[test]
+fn moving_a_ballot_entry_off_the_ballot_is_rejected() {
+    let client = client();
+    for uri in ["/ballot/entries/1/move?to=0", "/ballot/entries/1/move?to=1000"] {
+        let response = logged_in(client.post(uri), "alice").dispatch();
+        assert_error(response, Status::BadRequest, "invalid_request");
+    }
+}
+
+#[test]
+fn predictions_need_a_position_for_every_song() {
+    let client = client();
+    let song = |uri: &str, rank: Value| {
+        json!({
+            "key": null,
+            "name": "Song A",
+            "uri": uri,
+            "artist": "Artist 1",
+            "album_cover_url": "",
+            "rank": rank,
+        })
+    };
+    for rank in [Value::Null, json!(0), json!(101)] {
+        let response = logged_in(client.post("/predictions"), "alice")
+            .json(&json!([song("spotify:track:a", rank)]))
+            .dispatch();
+        assert_error(response, Status::BadRequest, "invalid_request");
+    }
+
+    // The same position or song twice is caught before it reaches the database
+    for (second_uri, second_rank) in [("spotify:track:b", 1), ("spotify:track:a", 2)] {
+        let response = logged_in(client.post("/predictions"), "alice")
+            .json(&json!([
+                song("spotify:track:a", json!(1)),
+                song(second_uri, json!(second_rank)),
+            ]))
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        let body: Value = response.into_json().unwrap();
+        assert_eq!(body["code"], "invalid_request");
+        assert!(body["error"].as_str().unwrap().starts_with("Rows 0 and 1"), "{}", body);
+    }
+}
+
+#[test]
+fn nicknames_are_length_limited() {
+    let client = client();
+    let response = logged_in(client.put("/profile/nickname"), "alice")
+        .json(&json!({ "nickname": "x".repeat(41) }))
+        .dispatch();
+    assert_error(response, Status::BadRequest, "invalid_request");
+}
+
+#[test]
+fn guests_need_a_name_and_a_numeric_pin() {
+    let client = client();
+    for (name, pin) in [
+        ("", "1234"),
+        ("  ", "1234"),
+        (&*"x".repeat(41), "1234"),
+        ("Guest", "12"),
+        ("Guest", "123456789"),
+        ("Guest", "abcd"),
+    ] {
+        let response = client
+            .post("/guest/join")
+            .json(&json!({ "code": "ABC", "name": name, "pin": pin }))
+            .dispatch();
+        assert_error(response, Status::BadRequest, "invalid_request");
+    }
+
+    let response = client
+        .post("/guest/join")
+        .header(ContentType::JSON)
+        .body("{\"code\": \"ABC\"}")
+        .dispatch();
+    assert_error(response, Status::BadRequest, "invalid_request");
+
+    let response = client
+        .post("/guest/login")
+        .header(ContentType::JSON)
+        .body("not json")
+        .dispatch();
+    assert_error(response, Status::BadRequest, "invalid_request");
+}
+
+#[test]
+fn guest_invites_need_sensible_limits() {
+    let client = client();
+    for request in [
+        json!({ "max_uses": 0 }),
+        json!({ "expires_in_days": 0 }),
+        json!({ "expires_in_days": 1_000_000_000_000_i64 }),
+    ] {
+        let response = as_admin(client.post("/admin/guest-invites"))
+            .json(&request)
+            .dispatch();
+        assert_error(response, Status::BadRequest, "invalid_request");
+    }
+}
+
+#[test]
+fn ballot_rules_must_be_consistent() {
+    let client = client();
+    for rules in [
+        json!({ "min_songs": 5, "max_songs": 2 }),
+        json!({ "max_songs": 1000 }),
+        json!({ "max_per_artist": 0 }),
+    ] {
+        let response = as_admin(client.put("/admin/polls/1/ballot-rules"))
+            .json(&rules)
+            .dispatch();
+        assert_error(response, Status::BadRequest, "invalid_request");
+    }
+
+    let response = as_admin(client.put("/admin/polls/1/eligibility"))
+        .json(&json!({ "eligible_from": "2024-12-01", "eligible_to": "2023-12-01" }))
+        .dispatch();
+    assert_error(response, Status::BadRequest, "invalid_request");
+}
+
+#[test]
+fn song_admin_rejects_bad_requests() {
+    let client = client();
+    let response = as_admin(client.get("/admin/songs/merge-suggestions?status=maybe")).dispatch();
+    assert_error(response, Status::BadRequest, "invalid_request");
+
+    let response = as_admin(client.post("/admin/songs/merge"))
+        .json(&json!({ "from_song_id": 3, "into_song_id": 3 }))
+        .dispatch();
+    assert_error(response, Status::BadRequest, "invalid_request");
+}
+
+#[test]
+fn official_results_csv_must_parse() {
+    let client = client();
+    let response = as_admin(client.post("/admin/polls/1/official-results"))
+        .header(ContentType::CSV)
+        .body("position,uri\nfirst,spotify:track:a\n")
+        .dispatch();
+    assert_error(response, Status::BadRequest, "invalid_request");
+}
+
+#[test]
+fn leaderboard_scoring_rules_are_bounded() {
+    let client = client();
+    for uri in [
+        "/predictions/leaderboard?exact=-1",
+        "/predictions/leaderboard?distance=1000000",
+        "/predictions/leaderboard?top_10=9223372036854775807",
+    ] {
+        assert_error(client.get(uri).dispatch(), Status::BadRequest, "invalid_request");
+    }
+}
+
+#[test]
+fn a_failed_spotify_login_redirects_to_fail() {
+    let client = client();
+    for uri in ["/callback", "/callback?code=", "/callback?error=access_denied"] {
+        let response = client.get(uri).dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+        assert_eq!(response.headers().get_one("Location"), Some("/fail"));
+    }
+}
+
+#[test]
+fn unknown_paths_return_a_json_404() {
+    let client = client();
+    let response = client.get("/no/such/page").dispatch();
+    assert_error(response, Status::NotFound, "not_found");
+}
+
+// Runs against a real Postgres when TEST_DATABASE_URL is set, and is skipped otherwise. It
+// creates a scratch database next to the one in the URL and drops it again afterwards.
+mod with_database {
+    use super::{app, ADMIN};
+    use crate::api::db;
+    use rocket::http::{ContentType, Cookie, Status};
+    use rocket::local::asynchronous::{Client, LocalRequest};
+    use serde_json::{json, Value};
+    use sqlx_postgres::PgPool;
+
+    fn logged_in<'c>(request: LocalRequest<'c>, provider_user_id: &str) -> LocalRequest<'c> {
+        let session = json!({ "provider": "spotify", "provider_user_id": provider_user_id });
+        request.private_cookie(Cookie::new("user", session.to_string()))
+    }
+
+    fn as_admin(request: LocalRequest<'_>) -> LocalRequest<'_> {
+        logged_in(request, ADMIN.trim_start_matches("spotify:user:"))
+    }
+
+    fn song(key: &str, rank: i32) -> Value {
+        json!({
+            "key": key,
+            "name": format!("Song {}", key),
+            "uri": format!("spotify:track:{}", key),
+            "artist": format!("Artist {}", key),
+            "album_cover_url": "",
+            "rank": rank,
+        })
+    }
+
+    async fn vote(client: &Client, user: &str, songs: &[Value]) {
+        let response = logged_in(client.put("/ballot"), user)
+            .header(ContentType::JSON)
+            .body(Value::from(songs.to_vec()).to_string())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok, "{:?}", response.into_string().await);
+        let response = logged_in(client.post("/ballot/submit"), user).dispatch().await;
+        assert_eq!(response.status(), Status::Ok, "{:?}", response.into_string().await);
+    }
+
+    async fn run_poll(client: &Client, name: &str, ballots: &[(&str, Vec<Value>)]) {
+        let uri = format!("/admin/polls?name={}", name);
+        let response = as_admin(client.post(uri)).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        for (user, songs) in ballots {
+            vote(client, user, songs).await;
+        }
+        let response = as_admin(client.post("/admin/polls/close")).dispatch().await;
+        assert_eq!(response.status(), Status::Ok, "{:?}", response.into_string().await);
+    }
+
+    #[rocket::async_test]
+    async fn wrapped_reads_the_closed_poll() {
+        let Ok(url) = std::env::var("TEST_DATABASE_URL") else {
+            return;
+        };
+        let admin_pool = PgPool::connect(&url).await.expect("TEST_DATABASE_URL connects");
+        let name = format!("hottest_test_{}", std::process::id());
+        sqlx::query(&format!("CREATE DATABASE {}", name))
+            .execute(&admin_pool)
+            .await
+            .unwrap();
+        let (base, _) = url.rsplit_once('/').expect("URL names a database");
+        let pool = PgPool::connect(&format!("{}/{}", base, name)).await.unwrap();
+        db::run_migrations(&pool).await.unwrap();
+        crate::DB_POOL.set(pool.clone()).unwrap();
+
+        std::env::set_var("ADMIN_USERS", ADMIN);
+        let client = Client::tracked(app(rocket::build(), "static")).await.unwrap();
+
+        run_poll(
+            &client,
+            "2025",
+            &[
+                ("alice", vec![song("a", 1), song("b", 2)]),
+                ("bob", vec![song("a", 1)]),
+            ],
+        )
+        .await;
+        run_poll(
+            &client,
+            "2026",
+            &[
+                ("alice", vec![song("c", 1), song("a", 2)]),
+                ("bob", vec![song("c", 1)]),
+            ],
+        )
+        .await;
+
+        // Closing reset both ballots, but wrapped still reads the 2026 poll
+        let response = logged_in(client.get("/wrapped"), "alice").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let wrapped: Value = response.into_json().await.unwrap();
+        assert_eq!(wrapped["poll"], "2026");
+        assert_eq!(wrapped["countdown_size"], 2);
+        let picks: Vec<(&str, i64, i64)> = wrapped["picks"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|pick| {
+                let name = pick["name"].as_str().unwrap();
+                (name, pick["position"].as_i64().unwrap(), pick["votes"].as_i64().unwrap())
+            })
+            .collect();
+        assert_eq!(picks, [("Song c", 1, 2), ("Song a", 2, 1)]);
+        assert_eq!(wrapped["most_contrarian"]["name"], "Song a");
+        assert_eq!(wrapped["taste_twin"]["user_name"], "spotify:user:bob");
+
+        let year_over_year = &wrapped["year_over_year"];
+        assert_eq!(year_over_year["previous_poll"], "2025");
+        assert_eq!(year_over_year["previous_best_position"], 1);
+        assert_eq!(
+            year_over_year["returning_songs"],
+            json!([{ "name": "Song a", "artist": "Artist a", "previous_rank": 1, "rank": 2 }])
+        );
+
+        pool.close().await;
+        sqlx::query(&format!("DROP DATABASE {} WITH (FORCE)", name))
+            .execute(&admin_pool)
+            .await
+            .unwrap();
+    }
+}
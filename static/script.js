@@ -1,6 +1,11 @@
 // State
 const rankedSongs = new Map();
 const songKeys = new Set();
+// Ballot version from the server, sent back so saves from a stale tab are rejected
+let ballotEtag = null;
+
+// The search being shown, so "More results" can fetch the page after it
+let searchPaging = null;
 
 // Drag and drop state
 let draggedItem = null;
@@ -13,22 +18,72 @@ const rankInput = document.getElementById('rank-input');
 const searchResults = document.getElementById('search-results');
 const rankingsList = document.getElementById('rankings-list');
 const saveBtn = document.getElementById('save-btn');
+const submitBtn = document.getElementById('submit-btn');
+const profileAvatar = document.getElementById('profile-avatar');
+const profileBtn = document.getElementById('profile-btn');
 
 // Initialize
 window.onload = () => {
   loadSavedSongs();
+  loadProfile();
   setupEventListeners();
 };
 
 function setupEventListeners() {
   searchForm.addEventListener('submit', handleSearch);
   saveBtn.addEventListener('click', handleSave);
+  submitBtn.addEventListener('click', handleSubmit);
+  profileBtn.addEventListener('click', handleNickname);
+}
+
+// Show who is logged in, by nickname if they've set one
+function renderProfile(profile) {
+  profileBtn.textContent = profile.label;
+  profileBtn.hidden = false;
+  if (profile.avatar_url) {
+    profileAvatar.src = profile.avatar_url;
+    profileAvatar.hidden = false;
+  }
+}
+
+async function loadProfile() {
+  try {
+    const response = await fetch('/profile');
+    if (response.ok) {
+      renderProfile(await response.json());
+    }
+  } catch (error) {
+    console.error('Error loading profile:', error);
+  }
+}
+
+// Blank clears the nickname and falls back to the Spotify display name
+async function handleNickname() {
+  const nickname = prompt('Nickname shown in results (leave blank for your Spotify name)', profileBtn.textContent);
+  if (nickname === null) return;
+
+  try {
+    const response = await fetch('/profile/nickname', {
+      method: 'PUT',
+      headers: { 'Content-Type': 'application/json' },
+      body: JSON.stringify({ nickname })
+    });
+    const body = await response.json();
+    if (!response.ok) {
+      alert(body.error);
+      return;
+    }
+    renderProfile(body);
+  } catch (error) {
+    console.error('Error saving nickname:', error);
+  }
 }
 
 // Load saved songs from server
 async function loadSavedSongs() {
   try {
     const response = await fetch('/songs');
+    ballotEtag = response.headers.get('ETag');
     const songs = await response.json();
 
     songs.forEach(song => {
@@ -42,6 +97,21 @@ async function loadSavedSongs() {
   }
 }
 
+// Fetches the next page of Spotify results for the current search and adds them to the list
+async function loadMoreResults() {
+  const { params, songs, nextOffset, rank } = searchPaging;
+  const pageParams = new URLSearchParams(params);
+  pageParams.set('offset', nextOffset);
+
+  const response = await fetch(`/search-songs?${pageParams}`);
+  const page = response.ok ? await response.json() : { songs: [], next_offset: null };
+  const shownUris = new Set(songs.map(song => song.uri));
+  searchPaging.songs = songs.concat(page.songs.filter(song => !shownUris.has(song.uri)));
+  searchPaging.nextOffset = page.next_offset;
+
+  renderSearchResults(searchPaging.songs, rank, page.next_offset !== null);
+}
+
 // Search for songs
 async function handleSearch(event) {
   event.preventDefault();
@@ -60,10 +130,17 @@ async function handleSearch(event) {
 
   try {
     const params = new URLSearchParams({ track: query, rank: rank });
-    const response = await fetch(`/search-songs?${params}`);
-    const songs = await response.json();
 
-    renderSearchResults(songs, rank);
+    // Songs the group already picked come back instantly, so show them while Spotify answers
+    const localResponse = await fetch(`/catalogue?${params}`);
+    const localSongs = localResponse.ok ? await localResponse.json() : [];
+    if (localSongs.length > 0) {
+      renderSearchResults(localSongs, rank);
+    }
+
+    // Spotify results fill in below, and if Spotify is unavailable the local ones stand
+    searchPaging = { params, songs: localSongs, nextOffset: 0, rank };
+    await loadMoreResults();
   } catch (error) {
     console.error('Error searching:', error);
     searchResults.innerHTML = `
@@ -75,7 +152,7 @@ async function handleSearch(event) {
 }
 
 // Render search results
-function renderSearchResults(songs, rank) {
+function renderSearchResults(songs, rank, hasMore = false) {
   if (songs.length === 0) {
     searchResults.innerHTML = `
       <div class="empty-state">
@@ -96,12 +173,13 @@ function renderSearchResults(songs, rank) {
         <button class="btn btn-secondary btn-icon add-song-btn">+</button>
       </div>
     </div>
-  `).join('');
+  `).join('') + (hasMore ? '<button class="btn btn-secondary" id="more-results-btn">More results</button>' : '');
 
   // Add click handlers
   searchResults.querySelectorAll('.song-card').forEach(card => {
     card.addEventListener('click', () => handleAddSong(card));
   });
+  document.getElementById('more-results-btn')?.addEventListener('click', loadMoreResults);
 }
 
 // Add song to rankings
@@ -195,34 +273,78 @@ function handleRemoveSong(rank) {
   }
 }
 
-// Save songs to server
-async function handleSave() {
-  if (rankedSongs.size < 10) {
-    alert('Please add 10 songs before saving!');
-    return;
+// Save songs to server, returns true when saved
+async function saveSongs() {
+  const headers = { 'Content-Type': 'application/json' };
+  if (ballotEtag) {
+    headers['If-Match'] = ballotEtag;
   }
+  const response = await fetch('/songs', {
+    method: 'POST',
+    headers,
+    body: JSON.stringify(Array.from(rankedSongs.values()))
+  });
+
+  if (response.status === 412) {
+    alert('Your list was changed in another tab. Reload the page to get the latest version.');
+    return false;
+  }
+  if (!response.ok) {
+    const body = await response.json().catch(() => ({}));
+    const details = (body.errors || []).map(error => error.message).join('\n');
+    alert((body.error || 'Error saving songs. Please try again.') + (details ? '\n' + details : ''));
+    return false;
+  }
+  ballotEtag = response.headers.get('ETag');
+  return true;
+}
 
+// Save the ballot as a draft, it won't count until it's submitted
+async function handleSave() {
   saveBtn.disabled = true;
   saveBtn.textContent = 'Saving...';
 
   try {
-    const response = await fetch('/songs', {
-      method: 'POST',
-      headers: { 'Content-Type': 'application/json' },
-      body: JSON.stringify(Array.from(rankedSongs.values()))
-    });
-
-    if (response.ok) {
-      alert('Songs saved successfully!');
-    } else {
-      throw new Error('Failed to save');
+    if (await saveSongs()) {
+      alert('Draft saved! Submit your list when you\'re happy with it.');
     }
   } catch (error) {
     console.error('Error saving:', error);
     alert('Error saving songs. Please try again.');
   } finally {
     saveBtn.disabled = false;
-    saveBtn.textContent = 'Save';
+    saveBtn.textContent = 'Save draft';
+  }
+}
+
+// Save and submit the ballot so it counts towards the results
+async function handleSubmit() {
+  if (rankedSongs.size < 10) {
+    alert('Please add 10 songs before submitting!');
+    return;
+  }
+
+  submitBtn.disabled = true;
+  submitBtn.textContent = 'Submitting...';
+
+  try {
+    if (!(await saveSongs())) {
+      return;
+    }
+    const response = await fetch('/ballot/submit', { method: 'POST' });
+    if (response.ok) {
+      alert('Songs submitted successfully!');
+    } else {
+      const body = await response.json().catch(() => ({}));
+      const details = (body.errors || []).map(error => error.message).join('\n');
+      alert((body.error || 'Error submitting songs. Please try again.') + (details ? '\n' + details : ''));
+    }
+  } catch (error) {
+    console.error('Error submitting:', error);
+    alert('Error submitting songs. Please try again.');
+  } finally {
+    submitBtn.disabled = false;
+    submitBtn.textContent = 'Submit';
   }
 }
 